@@ -36,6 +36,7 @@ fn main() {
     }
 
     let mem = KillMyArgv::new().expect("try init fail");
+    info!("diagnose:\n{}", mem.diagnose());
     fn printenv() {
         if false {
             for i in args() {
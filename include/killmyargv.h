@@ -0,0 +1,48 @@
+/*
+ * C ABI of killmyargv, exported when built with the `capi` feature.
+ *
+ * All functions share the process wide handle with the Rust API
+ * (`killmyargv::global()`), which is initialized on first use.
+ */
+
+#ifndef KILLMYARGV_H
+#define KILLMYARGV_H
+
+/* Generated by cbindgen from src/capi.rs, do not edit. */
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Initializes the process wide handle, returns 0 on success and -1 on failure.
+ *
+ * Calling it again is fine, as is skipping it, the other functions initialize on first use.
+ */
+int killmyargv_init(void);
+
+/**
+ * Sets the title to the NUL terminated `title`, returns 0 on success and -1 on failure.
+ *
+ * # Safety
+ * `title` has to be null or a valid C string.
+ */
+int killmyargv_set(const char *title);
+
+/**
+ * Restores the original title, returns 0 on success and -1 on failure.
+ */
+int killmyargv_revert(void);
+
+/**
+ * The longest title that fits, 0 when initialization failed.
+ */
+size_t killmyargv_max_len(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KILLMYARGV_H */
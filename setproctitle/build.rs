@@ -0,0 +1,12 @@
+fn main() {
+    // Rust can not define C-variadic functions on stable, setproctitle lives in C.
+    println!("cargo:rerun-if-changed=src/format.c");
+    println!("cargo:rerun-if-changed=src/exports.map");
+    cc::Build::new()
+        .file("src/format.c")
+        .compile("setproctitle_format");
+    // Nothing in Rust refers to it, and only Rust symbols are exported otherwise.
+    let map = concat!(env!("CARGO_MANIFEST_DIR"), "/src/exports.map");
+    println!("cargo:rustc-cdylib-link-arg=-Wl,--undefined=setproctitle");
+    println!("cargo:rustc-cdylib-link-arg=-Wl,--version-script={map}");
+}
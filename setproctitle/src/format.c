@@ -0,0 +1,28 @@
+/* setproctitle(3) itself, the title is set by lib.rs. */
+#include <stdarg.h>
+#include <stddef.h>
+#include <stdio.h>
+
+/* Same as SPT_BUFSIZE of FreeBSD. */
+#define TITLE_BUFSIZE 2048
+
+void killmyargv_setproctitle_title(const char *title, int with_progname);
+
+void setproctitle(const char *fmt, ...) {
+    char title[TITLE_BUFSIZE];
+    va_list ap;
+    int with_progname = 1;
+
+    if (fmt == NULL) {
+        killmyargv_setproctitle_title(NULL, 0);
+        return;
+    }
+    if (fmt[0] == '-') {
+        with_progname = 0;
+        fmt++;
+    }
+    va_start(ap, fmt);
+    vsnprintf(title, sizeof title, fmt, ap);
+    va_end(ap);
+    killmyargv_setproctitle_title(title, with_progname);
+}
@@ -0,0 +1,76 @@
+//! `setproctitle(3)` with the semantics of FreeBSD, for C programs on Linux.
+//!
+//! Link with `-lsetproctitle` or `LD_PRELOAD` the library:
+//!
+//! - `setproctitle("%s", "title")` sets `progname: title`.
+//! - a format starting with `-` leaves out the program name.
+//! - `setproctitle(NULL)` restores the original command line.
+//!
+//! Everything goes through [`killmyargv::global`].
+
+use std::{
+    ffi::{c_char, c_int, CStr, OsStr},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    sync::OnceLock,
+};
+
+// Taken before the first title replaces argv[0].
+fn progname() -> &'static [u8] {
+    static PROGNAME: OnceLock<Vec<u8>> = OnceLock::new();
+    PROGNAME.get_or_init(|| {
+        let argv0 = std::env::args_os().next().unwrap_or_default();
+        Path::new(&argv0)
+            .file_name()
+            .unwrap_or(OsStr::new(""))
+            .as_bytes()
+            .to_vec()
+    })
+}
+
+/// `void setproctitle_init(int argc, char *argv[], char *envp[])` of libbsd.
+///
+/// The process wide handle is initialized from the given arrays, without it
+/// `setproctitle` finds argv on its own.
+///
+/// # Safety
+/// `argv` and `envp` have to be the ones given to `main`.
+#[no_mangle]
+pub unsafe extern "C" fn setproctitle_init(
+    argc: c_int,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) {
+    progname();
+    if let Err(e) = unsafe { killmyargv::global_from_raw_parts(argc, argv, envp) } {
+        log::error!("setproctitle_init failed: {e}");
+    }
+}
+
+/// Called by `setproctitle` in format.c with the formatted title, null restores the original one.
+///
+/// Kept out of the exports by exports.map.
+///
+/// # Safety
+/// `title` has to be null or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn killmyargv_setproctitle_title(title: *const c_char, with_progname: c_int) {
+    let progname = progname();
+    let mem = match killmyargv::global() {
+        Ok(mem) => mem,
+        Err(e) => {
+            log::error!("setproctitle failed: {e}");
+            return;
+        }
+    };
+    if title.is_null() {
+        mem.revert();
+        return;
+    }
+    let title = unsafe { CStr::from_ptr(title) }.to_bytes();
+    if with_progname != 0 {
+        mem.set(&[progname, b": ", title].concat());
+    } else {
+        mem.set(title);
+    }
+}
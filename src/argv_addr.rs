@@ -1,18 +1,42 @@
 use super::EnvError;
-use crate::env_addr::envptr;
-use std::ffi::{c_char, c_int};
+use crate::env_addr::initial_envptr;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::procfs;
+use crate::stack_walk;
+use std::ffi::c_char;
 
 use log::{debug, trace};
 
 // init() copied from https://doc.rust-lang.org/src/std/sys/unix/args.rs.html#12-15
-// But what's the point?
-#[allow(unused)]
+// Used by KillMyArgv::from_raw_parts() to record what the embedder handed us.
 /// One-time global initialization.
 /// The above statement was made by rust std, and no one here is responsible for that statement.
 pub unsafe fn init(argc: isize, argv: *const *const u8) {
     imp::init(argc, argv)
 }
 
+/// Records the envp handed to KillMyArgv::from_raw_parts(), for captured_envp().
+pub(super) unsafe fn init_envp(envp: *const *const c_char) {
+    imp::init_envp(envp)
+}
+
+/// The argc/argv recorded by `imp`, from .init_array or the OS.
+pub(super) fn from_imp() -> Result<(usize, *const *const c_char), EnvError> {
+    let (argc, argv) = imp::argc_argv();
+    debug!("imp argc={argc}, argv={argv:?}, is null={}", argv.is_null());
+
+    if argv.is_null() || (unsafe { *argv }).is_null() {
+        Err(EnvError::InvalidArgvPointer)
+    } else {
+        Ok((argc as usize, argv))
+    }
+}
+
+/// The envp captured before main, null if it was not captured.
+pub(super) fn captured_envp() -> *const *const c_char {
+    imp::envp()
+}
+
 /// stack walking:
 /// https://github.com/rust-lang/rust/pull/66547#issuecomment-556013952
 /// and https://github.com/leo60228/libargs
@@ -20,88 +44,87 @@ pub unsafe fn init(argc: isize, argv: *const *const u8) {
 /// https://github.com/rust-lang/rust/issues/105999
 /// https://github.com/rust-lang/rust/pull/106001
 /// https://github.com/rust-lang/rust/commit/e97203c3f893893611818997bbeb0116ded2605f
-pub(super) fn addr() -> Result<(usize, *const *const c_char), EnvError> {
-    let (argc, argv) = imp::argc_argv();
-    debug!("imp argc={argc}, argv={argv:?}, is null={}", argv.is_null());
+pub(super) fn stack_walking(max_argc: usize) -> Result<(usize, *const *const c_char), EnvError> {
+    let envp = unsafe { initial_envptr().ok_or(EnvError::FailedToGetArgvPointer) }?;
+    stack_walk::walk(envp, max_argc)
+}
 
-    if argv.is_null() || (unsafe { *argv }).is_null() {
-        if cfg!(any(
-            feature = "compute_argv",
-            feature = "stack_walking",
-            feature = "force_walking"
-        )) {
-            debug!("failed from imp get argv, try compute/stackwalking");
-            comp_argv()
+/// Find argv from the auxiliary vector, regardless of where environ is now.
+pub(super) fn from_auxv(max_argc: usize) -> Result<(usize, *const *const c_char), EnvError> {
+    stack_walk::from_auxv(max_argc)
+}
+
+/// Compute argv from environ and the argc seen by std.
+pub(super) fn comp_argv() -> Result<(usize, *const *const c_char), EnvError> {
+    use std::{
+        env::args_os,
+        ffi::{CStr, OsStr},
+        os::unix::ffi::OsStrExt,
+    };
+    let envp = unsafe { initial_envptr().ok_or(EnvError::FailedToGetArgvPointer) }?;
+
+    let mut args = args_os();
+    trace!("std args: {:#?}", &args);
+    if args.len() == 0 {
+        debug!("std args is empty, can not compute argv");
+        return Err(EnvError::FailedToGetArgvPointer);
+    }
+
+    let std_argc = args.len();
+    // *environ[] == *argv[] + argc + 1, aka
+    // *argv[] = *environ[] - (argc + 1)
+    unsafe {
+        let comp_argv = envp.sub(std_argc + 1);
+        trace!(
+            "environ={envp:?}, std argc={std_argc:?}, computed argv={comp_argv:?}, point to: {:?}",
+            (*comp_argv)
+        );
+        if comp_argv.is_null() || (*comp_argv).is_null() {
+            return Err(EnvError::InvalidArgvPointer);
+        }
+
+        let frist = args.next().ok_or(EnvError::InvalidArgvPointer)?;
+        trace!("try read computed argv[0]");
+        let argv_frist = OsStr::from_bytes(CStr::from_ptr(*comp_argv).to_bytes());
+        trace!("computed argv[0]={argv_frist:?}, std argv[0]={frist:?}");
+        if argv_frist == frist {
+            Ok((std_argc, comp_argv))
         } else {
             Err(EnvError::InvalidArgvPointer)
         }
-    } else {
-        Ok((argc as usize, argv))
     }
 }
 
-// from: https://github.com/leo60228/libargs/blob/master/src/lib.rs#L16-L30
-// or `fn from_backtrace(...) -> (...)`?
-fn from_stack_walking(environ: *const *const c_char) -> (usize, *const *const c_char) {
-    let mut walk_environ = environ as *const usize;
-    walk_environ = walk_environ.wrapping_sub(1);
-    let mut i = 0;
-
-    loop {
-        let argc_ptr = walk_environ.wrapping_sub(1) as *const c_int;
-        let argc = unsafe { *argc_ptr };
-        if argc == i {
-            break (argc as usize, walk_environ as *const *const c_char);
+/// Find argv from the argument area the kernel reports in procfs.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn from_procfs() -> Result<(usize, *const *const c_char), EnvError> {
+    let stat = procfs::stat().ok_or(EnvError::ProcfsUnavailable)?;
+    let envp = unsafe { initial_envptr().ok_or(EnvError::FailedToGetArgvPointer) }?;
+    if stat.arg_end <= stat.arg_start {
+        return Err(EnvError::InvalidArgvPointer);
+    }
+    // Every argument is NUL terminated, as long as nobody rewrote the area yet.
+    let area = unsafe {
+        std::slice::from_raw_parts(stat.arg_start as *const u8, stat.arg_end - stat.arg_start)
+    };
+    let argc = area.iter().filter(|&&b| b == 0).count();
+    unsafe {
+        let argv = envp.sub(argc + 1);
+        trace!(
+            "arg_start={:#x}, argc={argc}, computed argv={argv:?}",
+            stat.arg_start
+        );
+        if argv.read() as usize == stat.arg_start && argv.add(argc).read().is_null() {
+            Ok((argc, argv))
+        } else {
+            Err(EnvError::InvalidArgvPointer)
         }
-        walk_environ = walk_environ.wrapping_sub(1);
-        i += 1;
     }
 }
 
-fn comp_argv() -> Result<(usize, *const *const i8), EnvError> {
-    let envp = unsafe { envptr().ok_or(EnvError::FailedToGetArgvPointer) }?;
-
-    if cfg!(feature = "force_walking") {
-        debug!("forge walking...");
-        return Ok(from_stack_walking(envp));
-    } else {
-        use std::{
-            env::args_os,
-            ffi::{CStr, OsStr},
-            os::unix::ffi::OsStrExt,
-        };
-        let mut args = args_os();
-        trace!("std args: {:#?}", &args);
-        if args.len() == 0 {
-            debug!("std args is empty, try stack walking...");
-            if cfg!(feature = "stack_walking") {
-                return Ok(from_stack_walking(envp));
-            } else {
-                return Err(EnvError::FailedToGetArgvPointer);
-            }
-        }
-
-        let std_argc = args.len();
-        // *environ[] == *argv[] + argc + 1, aka
-        // *argv[] = *environ[] - (argc + 1)
-        unsafe {
-            let comp_argv = envp.sub(std_argc + 1);
-            trace!("environ={envp:?}, std argc={std_argc:?}, computed argv={comp_argv:?}, point to: {:?}", (*comp_argv));
-            if comp_argv.is_null() || (*comp_argv).is_null() {
-                return Err(EnvError::InvalidArgvPointer);
-            }
-
-            let frist = args.next().ok_or(EnvError::InvalidArgvPointer)?;
-            trace!("try read computed argv[0]");
-            let argv_frist = OsStr::from_bytes(CStr::from_ptr(*comp_argv).to_bytes());
-            trace!("computed argv[0]={argv_frist:?}, std argv[0]={frist:?}");
-            if argv_frist == frist {
-                Ok((std_argc, comp_argv))
-            } else {
-                Err(EnvError::InvalidArgvPointer)
-            }
-        }
-    }
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn from_procfs() -> Result<(usize, *const *const c_char), EnvError> {
+    Err(EnvError::ProcfsUnavailable)
 }
 
 // imp::argc_argv() copied from: https://github.com/rust-lang/rust/blob/1.84.1/library/std/src/sys/pal/unix/args.rs#L96-L182
@@ -141,6 +164,9 @@ mod imp {
     // strings, which allows the code in this file to be very simple.
     static ARGC: AtomicIsize = AtomicIsize::new(0);
     static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
+    // The envp handed to the program, environ is reallocated by the first setenv
+    // and can no longer be used to find the original array after that.
+    static ENVP: AtomicPtr<*const u8> = AtomicPtr::new(ptr::null_mut());
 
     unsafe fn really_init(argc: isize, argv: *const *const u8) {
         // These don't need to be ordered with each other or other stores,
@@ -169,15 +195,56 @@ mod imp {
         extern "C" fn init_wrapper(
             argc: core::ffi::c_int,
             argv: *const *const u8,
-            _envp: *const *const u8,
+            envp: *const *const u8,
         ) {
             unsafe {
                 really_init(argc as isize, argv);
             }
+            // Objects loaded by dlopen get the environ of that moment instead,
+            // a setenv or clearenv before has moved it away from the initial stack.
+            // The kernel's envp still follows the NULL ending argv there.
+            let argc = argc.max(0) as usize;
+            let envp = if !argv.is_null() && unsafe { argv.add(argc).read() }.is_null() {
+                unsafe { argv.add(argc + 1) }
+            } else {
+                envp
+            };
+            ENVP.store(envp as *mut _, Ordering::Relaxed);
         }
         init_wrapper
     };
 
+    /// Other libcs (musl, uClibc...) call .init_array functions without arguments,
+    /// but environ still is the untouched envp at this point, and argc/argv sit
+    /// right below it on the initial stack. The auxv source covers late dlopen.
+    #[cfg(all(target_os = "linux", not(target_env = "gnu")))]
+    #[used]
+    #[link_section = ".init_array.00099"]
+    static ARGV_INIT_ARRAY: extern "C" fn() = {
+        extern "C" fn init_wrapper() {
+            if let Some(envp) = unsafe { crate::env_addr::envptr() } {
+                let max_argc = crate::stack_walk::DEFAULT_MAX_ARGC;
+                // Loaded by dlopen, environ may have been moved off the stack by now,
+                // it is only the initial envp when argv is found right below it.
+                if let Ok((argc, argv)) = crate::stack_walk::walk(envp, max_argc) {
+                    unsafe {
+                        really_init(argc as isize, argv.cast());
+                    }
+                    ENVP.store(envp as *mut _, Ordering::Relaxed);
+                }
+            }
+        }
+        init_wrapper
+    };
+
+    pub unsafe fn init_envp(envp: *const *const c_char) {
+        ENVP.store(envp as *mut _, Ordering::Relaxed);
+    }
+
+    pub fn envp() -> *const *const c_char {
+        ENVP.load(Ordering::Relaxed).cast()
+    }
+
     pub fn argc_argv() -> (isize, *const *const c_char) {
         // Load ARGC and ARGV, which hold the unmodified system-provided
         // argc/argv, so we can read the pointed-to memory without atomics or
@@ -217,6 +284,13 @@ mod imp {
         // done the work for us.
     }
 
+    pub unsafe fn init_envp(_envp: *const *const c_char) {}
+
+    pub fn envp() -> *const *const c_char {
+        // `_NSGetEnviron` is what everyone uses here, nothing was captured.
+        core::ptr::null()
+    }
+
     pub fn argc_argv() -> (isize, *const *const c_char) {
         extern "C" {
             // These functions are in crt_externs.h.
@@ -0,0 +1,181 @@
+use super::{ArgvSource, EnvError, KillMyArgv};
+use std::{
+    ffi::{c_char, c_int},
+    sync::Arc,
+};
+
+/// What to do when other threads are found running during initialization.
+///
+/// Relocating environ is only sound while the process is single-threaded,
+/// any other thread calling `getenv` at the same time may crash.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadPolicy {
+    /// Leave environ alone and only use the argv area.
+    #[default]
+    ArgvOnly,
+    /// Fail with [`EnvError::MultiThreaded`].
+    Error,
+    /// Clobber environ anyway, you have been warned.
+    Ignore,
+}
+
+/// What to do when the region was changed by someone else since the last write,
+/// see [`KillMyArgv::has_been_modified`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModifiedPolicy {
+    /// Log a warning and write anyway.
+    #[default]
+    Warn,
+    /// Leave the region alone, [`KillMyArgv::try_set`] fails with [`EnvError::RegionModified`].
+    Error,
+    /// Write anyway without a word.
+    Overwrite,
+}
+
+/// What every title set with [`KillMyArgv::set`] starts with,
+/// so `pgrep -f` and `pidof` still find the program by its name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TitlePrefix {
+    /// The title replaces the whole cmdline.
+    #[default]
+    None,
+    /// argv[0] as it was, followed by the title as the second element.
+    ///
+    /// The title is limited to the argv area then, the kernel only shows the first
+    /// element of a cmdline running into environ. Initialization fails with
+    /// [`EnvError::InsufficientCapacity`] when argv[0] leaves no room for a title,
+    /// e.g. without any other arguments.
+    Argv0,
+    /// The basename of argv[0], followed by the title as the second element,
+    /// limited like [`TitlePrefix::Argv0`].
+    Basename,
+    /// The basename of argv[0] and `": "` in front of the title, like BSD `setproctitle`.
+    Progname,
+}
+
+/// What to do when the arguments edited with [`KillMyArgv::set_arg`] and friends
+/// no longer fit into the argv area.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArgOverflow {
+    /// Leave the cmdline alone and fail with [`EnvError::InsufficientCapacity`].
+    #[default]
+    Error,
+    /// Show as much of the arguments as fits.
+    Truncate,
+}
+
+/// Configures how [`KillMyArgv`] is initialized.
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    pub(super) thread_policy: ThreadPolicy,
+    pub(super) sources: Vec<Arc<dyn ArgvSource>>,
+    pub(super) procfs_region: bool,
+    pub(super) revert_on_drop: bool,
+    pub(super) minimal_clobber: bool,
+    pub(super) cooperative: bool,
+    pub(super) modified_policy: ModifiedPolicy,
+    pub(super) title_prefix: TitlePrefix,
+    pub(super) arg_overflow: ArgOverflow,
+    // Only look, for diagnose(): argv, environ and the padding stay as they are.
+    pub(super) dry_run: bool,
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// Set what to do when other threads are running, see [`ThreadPolicy`].
+    pub fn thread_policy(mut self, policy: ThreadPolicy) -> Builder {
+        self.thread_policy = policy;
+        self
+    }
+
+    /// Append a source to try when looking for argv.
+    ///
+    /// Sources are tried in the order they were added, if none are added
+    /// [`default_sources`](crate::default_sources) is used.
+    /// argv is only looked up once per process, later builds reuse the first result.
+    pub fn source(mut self, source: impl ArgvSource + 'static) -> Builder {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    /// Replace the sources to try when looking for argv, see [`Builder::source`].
+    pub fn sources(mut self, sources: impl IntoIterator<Item = Arc<dyn ArgvSource>>) -> Builder {
+        self.sources = sources.into_iter().collect();
+        self
+    }
+
+    /// Take the argv and environ areas from the ones the kernel publishes
+    /// in `/proc/self/stat`, Linux only, so `max_len` is exactly what the kernel renders.
+    ///
+    /// The strings found through the pointers have to start at these areas and
+    /// stay within them, initialization fails with [`EnvError::ProcfsMismatch`] otherwise.
+    /// Strings no longer reachable from environ, e.g. after `unsetenv`, are part of the region.
+    pub fn procfs_region(mut self, enable: bool) -> Builder {
+        self.procfs_region = enable;
+        self
+    }
+
+    /// Call [`KillMyArgv::revert_full`] when the handle is dropped.
+    pub fn revert_on_drop(mut self, enable: bool) -> Builder {
+        self.revert_on_drop = enable;
+        self
+    }
+
+    /// Only write into the environ part of the region when a title needs it.
+    ///
+    /// `/proc/<pid>/environ` stays intact for titles fitting in the argv part,
+    /// and the environ bytes a longer title used are restored once a shorter one is set.
+    pub fn minimal_clobber(mut self, enable: bool) -> Builder {
+        self.minimal_clobber = enable;
+        self
+    }
+
+    /// What to do when another setproctitle implementation has already taken over
+    /// argv and environ: fail with [`EnvError::RegionTakenOver`] (the default), or
+    /// only write into the region it manages, from argv[0] to the end of environ.
+    ///
+    /// When cooperating neither the argv elements nor environ are touched.
+    pub fn cooperative(mut self, enable: bool) -> Builder {
+        self.cooperative = enable;
+        self
+    }
+
+    /// Set what to do when the region was changed behind our back, see [`ModifiedPolicy`].
+    pub fn modified_policy(mut self, policy: ModifiedPolicy) -> Builder {
+        self.modified_policy = policy;
+        self
+    }
+
+    /// Keep a prefix derived from argv[0] in front of every title, see [`TitlePrefix`].
+    ///
+    /// [`KillMyArgv::max_len`] is what is left for the title after the prefix.
+    pub fn title_prefix(mut self, prefix: TitlePrefix) -> Builder {
+        self.title_prefix = prefix;
+        self
+    }
+
+    /// Set what to do when edited arguments do not fit, see [`ArgOverflow`].
+    pub fn arg_overflow(mut self, policy: ArgOverflow) -> Builder {
+        self.arg_overflow = policy;
+        self
+    }
+
+    pub fn build(&self) -> Result<KillMyArgv, EnvError> {
+        KillMyArgv::from_builder(self)
+    }
+
+    /// Like [`KillMyArgv::from_raw_parts`], with the options of this builder.
+    /// # Safety
+    /// See [`KillMyArgv::from_raw_parts`].
+    pub unsafe fn build_from_raw_parts(
+        &self,
+        argc: c_int,
+        argv: *const *const c_char,
+        envp: *const *const c_char,
+    ) -> Result<KillMyArgv, EnvError> {
+        KillMyArgv::from_builder_raw_parts(self, argc, argv, envp)
+    }
+}
@@ -0,0 +1,66 @@
+//! C ABI over the process wide handle from [`global`], see `include/killmyargv.h`.
+//!
+//! Build your crate as a `cdylib` or `staticlib` with the `capi` feature enabled
+//! to export these, C and Rust code then share the same handle.
+
+use super::global;
+use std::ffi::{c_char, c_int, CStr};
+
+use log::error;
+
+/// Initializes the process wide handle, returns 0 on success and -1 on failure.
+///
+/// Calling it again is fine, as is skipping it, the other functions initialize on first use.
+#[no_mangle]
+pub extern "C" fn killmyargv_init() -> c_int {
+    match global() {
+        Ok(_) => 0,
+        Err(e) => {
+            error!("killmyargv_init failed: {e}");
+            -1
+        }
+    }
+}
+
+/// Sets the title to the NUL terminated `title`, returns 0 on success and -1 on failure.
+///
+/// # Safety
+/// `title` has to be null or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn killmyargv_set(title: *const c_char) -> c_int {
+    if title.is_null() {
+        return -1;
+    }
+    let title = unsafe { CStr::from_ptr(title) };
+    match global() {
+        Ok(mem) => match mem.try_set(title.to_bytes()) {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("killmyargv_set failed: {e}");
+                -1
+            }
+        },
+        Err(_) => -1,
+    }
+}
+
+/// Restores the original title, returns 0 on success and -1 on failure.
+#[no_mangle]
+pub extern "C" fn killmyargv_revert() -> c_int {
+    match global() {
+        Ok(mem) => match mem.try_revert() {
+            Ok(()) => 0,
+            Err(e) => {
+                error!("killmyargv_revert failed: {e}");
+                -1
+            }
+        },
+        Err(_) => -1,
+    }
+}
+
+/// The longest title that fits, 0 when initialization failed.
+#[no_mangle]
+pub extern "C" fn killmyargv_max_len() -> usize {
+    global().map_or(0, |mem| mem.max_len())
+}
@@ -0,0 +1,278 @@
+use super::{global::GLOBAL, EnvError, KillMyArgv};
+use std::{
+    fmt::{self, Display, Formatter, Write},
+    sync::TryLockError,
+};
+
+/// What initialization found, meant to be pasted into bug reports,
+/// see [`diagnose`] and [`KillMyArgv::diagnose`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The target triple the crate was built for.
+    pub target: &'static str,
+    /// The enabled cargo features of this crate.
+    pub features: Vec<&'static str>,
+    /// Why initialization failed or the handle could not be looked at,
+    /// the fields below are empty then.
+    pub error: Option<String>,
+    /// Name of the [`ArgvSource`](crate::ArgvSource) argv was found with.
+    pub source: Option<&'static str>,
+    /// argc as seen by `std::env::args`.
+    pub argc: usize,
+    /// argv elements found, and how many of them are part of the region.
+    pub argv_found: usize,
+    pub argv_available: usize,
+    /// environ elements found, and how many of them are part of the region.
+    pub environ_found: usize,
+    pub environ_available: usize,
+    /// Size of the contiguous argv and environ strings, with their NUL bytes.
+    pub argv_len: Option<usize>,
+    pub environ_len: Option<usize>,
+    /// Whether the environ strings directly follow the argv strings.
+    pub environ_adjacent: bool,
+    pub max_len: Option<usize>,
+    pub nonul_byte: Option<usize>,
+    /// `None` when `/proc/self/stat` can not be read.
+    pub procfs: Option<ProcfsCheck>,
+}
+
+/// The areas published in `/proc/self/stat` and whether they match the found ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcfsCheck {
+    pub arg_start: usize,
+    pub arg_end: usize,
+    pub env_start: usize,
+    pub env_end: usize,
+    pub argv_matches: bool,
+    pub environ_matches: bool,
+}
+
+/// Reports on the process wide handle from [`global`](crate::global) once it is initialized.
+///
+/// Before that, reports on what initializing it would find, without
+/// initializing it or writing to argv and environ.
+///
+/// Never waits for the handle, while it is locked, by this thread or
+/// another one, the report only carries an error saying so.
+pub fn diagnose() -> Report {
+    let result = match GLOBAL.get() {
+        Some(Ok(mem)) => {
+            return match mem.try_lock() {
+                Ok(mem) => mem.diagnose(),
+                Err(TryLockError::Poisoned(e)) => e.into_inner().diagnose(),
+                Err(TryLockError::WouldBlock) => Report {
+                    error: Some("The global handle is locked.".to_owned()),
+                    ..Report::empty()
+                },
+            }
+        }
+        Some(Err(e)) => Err(e.clone()),
+        None => KillMyArgv::dry_run(),
+    };
+    match result {
+        Ok(mem) => mem.diagnose(),
+        Err(e) => Report::failed(&e),
+    }
+}
+
+impl KillMyArgv {
+    /// Reports on what this handle found at initialization.
+    pub fn diagnose(&self) -> Report {
+        let layout = self.layout();
+        let in_region =
+            |entries: &[crate::Entry]| entries.iter().filter(|e| e.offset.is_some()).count();
+        let procfs = procfs_check().map(|mut check| {
+            let argv = (layout.argv.start as usize, layout.argv.end() as usize);
+            check.argv_matches = argv == (check.arg_start, check.arg_end);
+            check.environ_matches = layout.environ.is_some_and(|environ| {
+                (environ.start as usize, environ.end() as usize) == (check.env_start, check.env_end)
+            });
+            check
+        });
+        Report {
+            source: Some(self.source()),
+            argv_found: layout.argv_entries.len(),
+            argv_available: in_region(&layout.argv_entries),
+            environ_found: layout.environ_entries.len(),
+            environ_available: in_region(&layout.environ_entries),
+            argv_len: Some(layout.argv.len),
+            environ_len: layout.environ.map(|environ| environ.len),
+            environ_adjacent: layout.gap == Some(0),
+            max_len: Some(self.max_len()),
+            nonul_byte: self.nonul_byte(),
+            procfs,
+            ..Report::empty()
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn procfs_check() -> Option<ProcfsCheck> {
+    super::procfs::stat().map(|stat| ProcfsCheck {
+        arg_start: stat.arg_start,
+        arg_end: stat.arg_end,
+        env_start: stat.env_start,
+        env_end: stat.env_end,
+        argv_matches: false,
+        environ_matches: false,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn procfs_check() -> Option<ProcfsCheck> {
+    None
+}
+
+fn features() -> Vec<&'static str> {
+    [
+        ("auto_init", cfg!(feature = "auto_init")),
+        ("capi", cfg!(feature = "capi")),
+        ("clobber_environ", cfg!(feature = "clobber_environ")),
+        ("compute_argv", cfg!(feature = "compute_argv")),
+        ("stack_walking", cfg!(feature = "stack_walking")),
+        ("force_walking", cfg!(feature = "force_walking")),
+        (
+            "replace_argv_element",
+            cfg!(feature = "replace_argv_element"),
+        ),
+        (
+            "replace_environ_element",
+            cfg!(feature = "replace_environ_element"),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+impl Report {
+    fn failed(e: &EnvError) -> Report {
+        Report {
+            error: Some(e.to_string()),
+            ..Report::empty()
+        }
+    }
+
+    fn empty() -> Report {
+        Report {
+            target: env!("TARGET"),
+            features: features(),
+            error: None,
+            source: None,
+            argc: std::env::args_os().len(),
+            argv_found: 0,
+            argv_available: 0,
+            environ_found: 0,
+            environ_available: 0,
+            argv_len: None,
+            environ_len: None,
+            environ_adjacent: false,
+            max_len: None,
+            nonul_byte: None,
+            procfs: None,
+        }
+    }
+
+    /// Renders the report as a single line JSON object.
+    pub fn to_json(&self) -> String {
+        fn string(s: &str) -> String {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    c if c.is_control() => {
+                        let _ = write!(out, "\\u{:04x}", c as u32);
+                    }
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        fn opt<T: Display>(v: Option<T>) -> String {
+            v.map_or_else(|| "null".to_owned(), |v| v.to_string())
+        }
+
+        let features = self
+            .features
+            .iter()
+            .map(|f| string(f))
+            .collect::<Vec<_>>()
+            .join(",");
+        let procfs = self.procfs.map_or_else(
+            || "null".to_owned(),
+            |p| {
+                format!(
+                    "{{\"arg_start\":{},\"arg_end\":{},\"env_start\":{},\"env_end\":{},\"argv_matches\":{},\"environ_matches\":{}}}",
+                    p.arg_start, p.arg_end, p.env_start, p.env_end, p.argv_matches, p.environ_matches
+                )
+            },
+        );
+        format!(
+            "{{\"target\":{},\"features\":[{features}],\"error\":{},\"source\":{},\"argc\":{},\"argv_found\":{},\"argv_available\":{},\"environ_found\":{},\"environ_available\":{},\"argv_len\":{},\"environ_len\":{},\"environ_adjacent\":{},\"max_len\":{},\"nonul_byte\":{},\"procfs\":{procfs}}}",
+            string(self.target),
+            opt(self.error.as_deref().map(string)),
+            opt(self.source.map(string)),
+            self.argc,
+            self.argv_found,
+            self.argv_available,
+            self.environ_found,
+            self.environ_available,
+            opt(self.argv_len),
+            opt(self.environ_len),
+            self.environ_adjacent,
+            opt(self.max_len),
+            opt(self.nonul_byte),
+        )
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fn opt<T: Display>(v: Option<T>) -> String {
+            v.map_or_else(|| "-".to_owned(), |v| v.to_string())
+        }
+
+        writeln!(f, "target:           {}", self.target)?;
+        writeln!(f, "features:         {}", self.features.join(", "))?;
+        if let Some(error) = &self.error {
+            return writeln!(f, "error:            {error}");
+        }
+        writeln!(f, "source:           {}", opt(self.source))?;
+        writeln!(
+            f,
+            "argv:             {} of {} found in region, argc {}",
+            self.argv_available, self.argv_found, self.argc
+        )?;
+        writeln!(
+            f,
+            "environ:          {} of {} found in region",
+            self.environ_available, self.environ_found
+        )?;
+        writeln!(
+            f,
+            "region:           argv {} bytes, environ {} bytes, adjacent: {}",
+            opt(self.argv_len),
+            opt(self.environ_len),
+            self.environ_adjacent
+        )?;
+        writeln!(f, "max_len:          {}", opt(self.max_len))?;
+        writeln!(f, "nonul_byte:       {}", opt(self.nonul_byte))?;
+        match &self.procfs {
+            Some(p) => {
+                writeln!(
+                    f,
+                    "procfs argv:      {:#x} -> {:#x}, matches: {}",
+                    p.arg_start, p.arg_end, p.argv_matches
+                )?;
+                writeln!(
+                    f,
+                    "procfs environ:   {:#x} -> {:#x}, matches: {}",
+                    p.env_start, p.env_end, p.environ_matches
+                )
+            }
+            None => writeln!(f, "procfs:           unavailable"),
+        }
+    }
+}
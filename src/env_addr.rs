@@ -1,3 +1,4 @@
+use crate::argv_addr;
 use std::ffi::c_char;
 
 #[cfg(feature = "clobber_environ")]
@@ -52,17 +53,34 @@ pub(super) unsafe fn envptr() -> Option<*const *const c_char> {
     }
 }
 
+// environ may have been reallocated by setenv since startup,
+// prefer the envp captured before main when there is one.
+pub(super) unsafe fn initial_envptr() -> Option<*const *const c_char> {
+    let envp = argv_addr::captured_envp();
+    if envp.is_null() {
+        envptr()
+    } else {
+        Some(envp)
+    }
+}
+
+pub(super) fn addr() -> Option<(usize, *const *const c_char)> {
+    addr_from(unsafe { initial_envptr()? })
+}
+
 // environ is not used by default.
 #[cfg(not(feature = "clobber_environ"))]
-pub(super) fn addr() -> Option<(usize, *const *const c_char)> {
+pub(super) fn addr_from(_envp: *const *const c_char) -> Option<(usize, *const *const c_char)> {
     None
 }
 
 #[cfg(feature = "clobber_environ")]
 // copied from https://github.com/rust-lang/rust/blob/1.84.0/library/src/std/sys/pal/unix/os.rs.html#L626-L658
-pub(super) fn addr() -> Option<(usize, *const *const c_char)> {
+pub(super) fn addr_from(envp: *const *const c_char) -> Option<(usize, *const *const c_char)> {
+    if envp.is_null() {
+        return None;
+    }
     unsafe {
-        let envp = *environ();
         let mut environ = envp;
         // I often forget: Where did the number of elements go?
         debug!("environ={environ:?}, point to: {:?}", *environ);
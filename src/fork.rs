@@ -0,0 +1,63 @@
+use super::{
+    global::{GLOBAL, HOLDING, INIT, INITIALIZING},
+    ARGV_MEM,
+};
+use std::{
+    any::Any,
+    cell::RefCell,
+    sync::{Once, PoisonError},
+};
+
+use log::{debug, warn};
+
+// A forked child only has a copy of the thread calling fork, a lock held by
+// any other thread at that moment would stay locked in the child forever.
+// So the forking thread takes every lock before fork and lets go of them
+// on both sides afterwards, in the parent and in the child.
+// Locks the forking thread holds itself are not taken again, its copy in the
+// child lets go of them as usual.
+thread_local! {
+    static HELD: RefCell<Vec<Box<dyn Any>>> = const { RefCell::new(Vec::new()) };
+}
+
+static REGISTER: Once = Once::new();
+
+/// Installs the fork handlers, once per process.
+pub(super) fn register() {
+    REGISTER.call_once(|| {
+        let ret = unsafe { libc::pthread_atfork(Some(prepare), Some(release), Some(release)) };
+        if ret != 0 {
+            warn!("pthread_atfork failed: {ret}, forking while the handle is in use may deadlock the child");
+        } else {
+            debug!("fork handlers installed");
+        }
+    });
+}
+
+// INIT and the global handle are locked before ARGV_MEM, as its initialization does.
+unsafe extern "C" fn prepare() {
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        if !INITIALIZING.get() {
+            // Waits for an initialization by another thread to finish.
+            held.push(Box::new(
+                INIT.lock().unwrap_or_else(PoisonError::into_inner),
+            ));
+        }
+        if let Some(Ok(mem)) = GLOBAL.get().filter(|_| !HOLDING.get()) {
+            held.push(Box::new(mem.lock().unwrap_or_else(PoisonError::into_inner)));
+        }
+        if let Some(argv_info) = ARGV_MEM.get() {
+            held.push(Box::new(
+                argv_info.lock().unwrap_or_else(PoisonError::into_inner),
+            ));
+        }
+    });
+}
+
+unsafe extern "C" fn release() {
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        while held.pop().is_some() {}
+    });
+}
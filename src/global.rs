@@ -0,0 +1,136 @@
+use super::{EnvError, KillMyArgv};
+use std::{
+    cell::Cell,
+    ffi::{c_char, c_int},
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, OnceLock, PoisonError},
+};
+
+use log::debug;
+
+pub(super) static GLOBAL: OnceLock<Result<Mutex<KillMyArgv>, EnvError>> = OnceLock::new();
+
+// Held while GLOBAL is initialized, so a fork waits until it is done, see fork.rs.
+pub(super) static INIT: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    // Whether this thread initializes GLOBAL or holds its guard right now,
+    // a fork by this very thread must not wait for it.
+    pub(super) static INITIALIZING: Cell<bool> = const { Cell::new(false) };
+    pub(super) static HOLDING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn init() -> &'static Result<Mutex<KillMyArgv>, EnvError> {
+    init_with(KillMyArgv::new)
+}
+
+fn init_with(
+    new: impl FnOnce() -> Result<KillMyArgv, EnvError>,
+) -> &'static Result<Mutex<KillMyArgv>, EnvError> {
+    if let Some(result) = GLOBAL.get() {
+        return result;
+    }
+    super::fork::register();
+    let _init = INIT.lock().unwrap_or_else(PoisonError::into_inner);
+    GLOBAL.get_or_init(|| {
+        INITIALIZING.set(true);
+        let result = new().map(Mutex::new);
+        INITIALIZING.set(false);
+        debug!("global handle initialized: {}", result.is_ok());
+        result
+    })
+}
+
+/// The process wide handle from [`global`], locked until this is dropped.
+#[derive(Debug)]
+pub struct GlobalGuard {
+    guard: MutexGuard<'static, KillMyArgv>,
+}
+
+impl GlobalGuard {
+    fn lock(mem: &'static Mutex<KillMyArgv>) -> GlobalGuard {
+        let guard = mem.lock().unwrap_or_else(PoisonError::into_inner);
+        HOLDING.set(true);
+        GlobalGuard { guard }
+    }
+}
+
+impl Deref for GlobalGuard {
+    type Target = KillMyArgv;
+
+    fn deref(&self) -> &KillMyArgv {
+        &self.guard
+    }
+}
+
+impl DerefMut for GlobalGuard {
+    fn deref_mut(&mut self) -> &mut KillMyArgv {
+        &mut self.guard
+    }
+}
+
+impl Drop for GlobalGuard {
+    fn drop(&mut self) {
+        HOLDING.set(false);
+    }
+}
+
+/// Get the process wide handle, it is initialized on first use,
+/// or before main with the `auto_init` feature.
+///
+/// An error from initialization is kept and returned on every call.
+///
+/// Forking is fine at any time, while this or other threads hold the handle
+/// or initialize it, the child gets it unlocked and initialized.
+pub fn global() -> Result<GlobalGuard, EnvError> {
+    match init() {
+        Ok(mem) => Ok(GlobalGuard::lock(mem)),
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Like [`global`], initializing the handle from the argc/argv/envp given to a
+/// C `main` when it is not yet, see [`KillMyArgv::from_raw_parts`].
+///
+/// A handle initialized before with another argv fails with [`EnvError::ArgvMismatch`].
+/// # Safety
+/// See [`KillMyArgv::from_raw_parts`].
+pub unsafe fn global_from_raw_parts(
+    argc: c_int,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> Result<GlobalGuard, EnvError> {
+    let mem = match init_with(|| KillMyArgv::from_raw_parts(argc, argv, envp)) {
+        Ok(mem) => GlobalGuard::lock(mem),
+        Err(e) => return Err(e.clone()),
+    };
+    if mem.argv_ptr != argv {
+        return Err(EnvError::ArgvMismatch {
+            ptr: argv,
+            found_by: mem.source(),
+        });
+    }
+    Ok(mem)
+}
+
+/// Runs after the argv capture in `argv_addr::imp` and before any static
+/// initializer of the program gets a chance to spawn threads.
+#[cfg(all(feature = "auto_init", not(target_vendor = "apple")))]
+#[used]
+#[link_section = ".init_array.00100"]
+static AUTO_INIT_ARRAY: extern "C" fn() = {
+    extern "C" fn auto_init() {
+        init();
+    }
+    auto_init
+};
+
+#[cfg(all(feature = "auto_init", target_vendor = "apple"))]
+#[used]
+#[link_section = "__DATA,__mod_init_func"]
+static AUTO_INIT_ARRAY: extern "C" fn() = {
+    extern "C" fn auto_init() {
+        init();
+    }
+    auto_init
+};
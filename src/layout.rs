@@ -0,0 +1,93 @@
+use super::MemInfo;
+use std::ffi::{c_char, CStr};
+
+/// `len` bytes starting at `start`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: *const u8,
+    pub len: usize,
+}
+
+impl Region {
+    // end_addr of a MemInfo is the NUL byte of its last string.
+    fn from_mem(mem: &MemInfo) -> Region {
+        Region {
+            start: mem.begin_addr as *const u8,
+            len: mem.end_addr as usize + 1 - mem.begin_addr as usize,
+        }
+    }
+
+    /// One past the last byte.
+    pub fn end(&self) -> *const u8 {
+        self.start.wrapping_add(self.len)
+    }
+}
+
+/// Where an argument or variable was found at initialization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Index in argv or environ.
+    pub index: usize,
+    /// Offset from the start of the argv region, `None` for strings outside of it,
+    /// see [`KillMyArgv::stray_pointers`](crate::KillMyArgv::stray_pointers).
+    pub offset: Option<usize>,
+    /// Length without the terminating NUL byte.
+    pub len: usize,
+    /// The pointer found in argv or environ, before anything was replaced.
+    pub ptr: *const c_char,
+}
+
+/// The memory layout found at initialization, see [`KillMyArgv::layout`](crate::KillMyArgv::layout).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// What [`KillMyArgv::set`](crate::KillMyArgv::set) writes into.
+    pub region: Region,
+    /// The contiguous argv strings, with their NUL bytes.
+    pub argv: Region,
+    /// The contiguous environ strings, with their NUL bytes, if they are part of the region.
+    pub environ: Option<Region>,
+    /// Bytes between the end of argv and the start of environ,
+    /// `None` when environ is not after argv.
+    pub gap: Option<usize>,
+    pub argv_entries: Vec<Entry>,
+    pub environ_entries: Vec<Entry>,
+}
+
+unsafe impl Send for Layout {}
+
+impl Layout {
+    pub(super) fn new(region: Region, argv_mem: &MemInfo, env_mem: Option<&MemInfo>) -> Layout {
+        let argv = Region::from_mem(argv_mem);
+        let environ = env_mem.map(Region::from_mem);
+        let gap =
+            environ.and_then(|environ| (environ.start as usize).checked_sub(argv.end() as usize));
+        Layout {
+            region,
+            argv,
+            environ,
+            gap,
+            argv_entries: entries(argv_mem, region),
+            environ_entries: env_mem.map_or_else(Vec::new, |mem| entries(mem, region)),
+        }
+    }
+}
+
+// The expected input is always the checked output of from_addr()
+fn entries(mem: &MemInfo, region: Region) -> Vec<Entry> {
+    (0..mem.count)
+        .map(|index| unsafe { mem.ptr.add(index).read() })
+        .take_while(|ptr| !ptr.is_null())
+        .enumerate()
+        .map(|(index, ptr)| {
+            let in_region = (region.start..region.end()).contains(&ptr.cast());
+            Entry {
+                index,
+                offset: (ptr as usize)
+                    .checked_sub(region.start as usize)
+                    .filter(|_| in_region),
+                len: unsafe { CStr::from_ptr(ptr) }.to_bytes().len(),
+                ptr,
+            }
+        })
+        .collect()
+}
@@ -1,14 +1,37 @@
 mod argv_addr;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod diagnose;
 mod env_addr;
+mod fork;
+mod global;
+mod layout;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod procfs;
+mod source;
+mod stack_walk;
+mod threads;
+
+pub use builder::{ArgOverflow, Builder, ModifiedPolicy, ThreadPolicy, TitlePrefix};
+pub use diagnose::{diagnose, ProcfsCheck, Report};
+pub use global::{global, global_from_raw_parts, GlobalGuard};
+pub use layout::{Entry, Layout, Region};
+pub use source::{default_sources, ArgvSource, Auxv, Computed, InitArray, Procfs, StackWalking};
 
 #[cfg(all(feature = "clobber_environ", feature = "replace_environ_element"))]
 use std::env::{remove_var, set_var, vars_os};
 use std::{
+    cell::{Cell, RefCell},
     cmp,
-    ffi::{c_char, CStr, CString, OsStr},
-    os::unix::ffi::OsStrExt,
+    collections::HashSet,
+    ffi::{c_char, c_int, CStr, CString, OsStr},
+    hash::{DefaultHasher, Hasher},
+    os::unix::{ffi::OsStrExt, process::CommandExt},
+    path::PathBuf,
+    process::Command,
     slice,
-    sync::{Mutex, OnceLock},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
 };
 
 use log::{debug, error, trace, warn};
@@ -22,9 +45,26 @@ const OS_MAX_LEN_LIMIT: usize = if cfg!(any(target_os = "illumos", target_os = "
     usize::MAX
 };
 
-static ARGV_MEM: OnceLock<Mutex<MemInfo>> = OnceLock::new();
+// Added by ensure_capacity() to the environment of the re-executed program,
+// removed again once initialization has accounted for it.
+const PADDING_VAR: &str = "KILLMYARGV_PADDING";
+
+// The first argv found wins, together with the name of its source.
+static ARGV_MEM: OnceLock<Mutex<(MemInfo, &'static str)>> = OnceLock::new();
+
+// The copies we pointed argv and environ at ourselves, moving them out of
+// the region looks just like another implementation taking it over.
+struct Relocated {
+    argv: Vec<usize>,
+    environ: Vec<usize>,
+}
+
+static RELOCATED: Mutex<Relocated> = Mutex::new(Relocated {
+    argv: Vec::new(),
+    environ: Vec::new(),
+});
 
-#[derive(Error, Debug)]
+#[derive(Error, Clone, Debug)]
 pub enum EnvError {
     #[error("The *argv[] points to an invalid memory address.")]
     InvalidArgvPointer,
@@ -36,9 +76,48 @@ pub enum EnvError {
     PonitToNull { ptr: *const *const c_char },
     #[error("The pointer as null.")]
     NullPointer,
+    #[error("No argv source could find *argv[].")]
+    NoArgvSource,
+    #[error("procfs is not available.")]
+    ProcfsUnavailable,
+    #[error("The {area} area {found_start:#x} -> {found_end:#x} does not match {start:#x} -> {end:#x} from /proc/self/stat.")]
+    ProcfsMismatch {
+        area: &'static str,
+        start: usize,
+        end: usize,
+        found_start: usize,
+        found_end: usize,
+    },
+    #[error("Stack walking from `{ptr:?}` failed: {reason}.")]
+    StackWalkFailed {
+        ptr: *const *const c_char,
+        reason: &'static str,
+    },
+    #[error("Refusing to clobber environ while {threads} threads are running.")]
+    MultiThreaded { threads: usize },
+    #[error("The program was started with an empty argv, there is no region to write into.")]
+    EmptyArgv,
+    #[error("argv and environ were taken over by another setproctitle implementation.")]
+    RegionTakenOver,
+    #[error("The region was modified by someone else since it was last written.")]
+    RegionModified,
+    #[error("Only {max_len} bytes are available for the title, {needed} are needed.")]
+    InsufficientCapacity { needed: usize, max_len: usize },
+    #[error("Re-executing the program failed with errno {errno}.")]
+    ReexecFailed { errno: i32 },
+    #[error("There is no argument {index}, only {len}.")]
+    ArgIndexOutOfRange { index: usize, len: usize },
+    #[error("An argument can not contain a NUL byte.")]
+    NulInArg,
+    #[error("The given argv `{ptr:?}` is not the one found before by the {found_by} source.")]
+    ArgvMismatch {
+        ptr: *const *const c_char,
+        found_by: &'static str,
+    },
 }
 
 unsafe impl Send for EnvError {}
+unsafe impl Sync for EnvError {}
 
 #[derive(Debug)]
 pub struct KillMyArgv {
@@ -47,9 +126,44 @@ pub struct KillMyArgv {
     max_len: usize,
     saved_argv: Vec<CString>,
     nonul_byte: Option<usize>,
+    source: &'static str,
+    strays: Vec<StrayPointer>,
+    layout: Layout,
+    // The region and the argv array as found, for revert_full().
+    saved_region: Vec<u8>,
+    argv_ptr: *const *const c_char,
+    revert_on_drop: bool,
+    minimal_clobber: bool,
+    modified_policy: ModifiedPolicy,
+    // Checksum of the region after our last write, or as found.
+    last_written: Cell<u64>,
+    // Whether ensure_capacity() already re-executed the program.
+    padded: bool,
+    // Whether padding environ grows the region, environ being part of it
+    // or empty with nothing else keeping it out.
+    paddable: bool,
+    // Written in front of every title, counted in the region's max_len.
+    prefix: Vec<u8>,
+    // The arguments as currently shown, for set_arg() and friends.
+    args: RefCell<Vec<Vec<u8>>>,
+    arg_overflow: ArgOverflow,
+}
+
+unsafe impl Send for KillMyArgv {}
+
+/// An argv or environ element that does not point into the writable region,
+/// e.g. moved by a commandline parser or another setproctitle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StrayPointer {
+    /// `argv` or `environ`.
+    pub area: &'static str,
+    pub index: usize,
+    pub ptr: *const c_char,
 }
 
-#[derive(Clone, Copy, Debug)]
+unsafe impl Send for StrayPointer {}
+
+#[derive(Clone, Debug)]
 struct MemInfo {
     begin_addr: *const c_char,
     end_addr: *const c_char,
@@ -58,14 +172,24 @@ struct MemInfo {
     count: usize,
     #[allow(unused)]
     ptr: *const *const c_char,
+    // Elements after the contiguous ones, pointing outside of begin_addr..=end_addr.
+    strays: Vec<StrayPointer>,
 }
 
 unsafe impl Send for MemInfo {}
 
-unsafe fn from_addr(count: usize, ptr: *const *const c_char) -> Result<MemInfo, EnvError> {
+unsafe fn from_addr(
+    area: &'static str,
+    count: usize,
+    ptr: *const *const c_char,
+) -> Result<MemInfo, EnvError> {
     if ptr.is_null() {
         return Err(EnvError::NullPointer);
     } else if ptr.read().is_null() {
+        // argc == 0, only possible on kernels not inserting an empty argv[0] for us.
+        if area == "argv" {
+            return Err(EnvError::EmptyArgv);
+        }
         return Err(EnvError::PonitToNull { ptr });
     }
 
@@ -107,28 +231,150 @@ unsafe fn from_addr(count: usize, ptr: *const *const c_char) -> Result<MemInfo,
         available = i;
     }
 
+    // Only strings laid out back to back, each right after the previous NUL,
+    // are known to be ours. The kernel does that, but anyone may have moved
+    // some of them since.
     let begin_addr = unsafe { ptr.read() };
-    let mut end_addr = unsafe { ptr.add(available).read() };
-    end_addr = unsafe { end_addr.add(CStr::from_ptr(end_addr).to_bytes().len()) };
+    let mut end_addr = unsafe { begin_addr.add(CStr::from_ptr(begin_addr).to_bytes().len()) };
+    let mut contiguous = 1;
+    while contiguous <= available {
+        let next = unsafe { ptr.add(contiguous).read() };
+        if next != unsafe { end_addr.add(1) } {
+            warn!(
+                "string[{contiguous}]={next:?} does not follow {end_addr:?}, the region ends there"
+            );
+            break;
+        }
+        end_addr = unsafe { next.add(CStr::from_ptr(next).to_bytes().len()) };
+        contiguous += 1;
+    }
+    let begin_addr = if area == "argv" {
+        with_loader_slots(begin_addr, end_addr)
+    } else {
+        begin_addr
+    };
+    let strays = (contiguous..=available)
+        .map(|index| StrayPointer {
+            area,
+            index,
+            ptr: unsafe { ptr.add(index).read() },
+        })
+        .filter(|stray| !(begin_addr..=end_addr).contains(&stray.ptr))
+        .collect::<Vec<_>>();
 
-    debug!("given count={count}, available count={available}, ptr={ptr:?}, range: {begin_addr:?} -> {end_addr:?}");
+    debug!("given count={count}, available count={available}, contiguous={contiguous}, ptr={ptr:?}, range: {begin_addr:?} -> {end_addr:?}, strays: {strays:?}");
     if count > 0 {
         Ok(MemInfo {
             begin_addr,
             end_addr,
             count: available + 1,
             ptr,
+            strays,
         })
     } else {
         Err(EnvError::FailedToGetString { ptr })
     }
 }
 
+// Run as `ld.so ./prog args`, the loader drops its own arguments from argv,
+// but the area the kernel renders still starts with them.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn with_loader_slots(begin_addr: *const c_char, end_addr: *const c_char) -> *const c_char {
+    let Some(stat) = procfs::stat() else {
+        return begin_addr;
+    };
+    let begin = begin_addr as usize;
+    if stat.arg_end != end_addr as usize + 1 || stat.arg_start >= begin {
+        return begin_addr;
+    }
+    // Has to be whole strings, the last one ending right before argv[0].
+    let slots =
+        unsafe { slice::from_raw_parts(stat.arg_start as *const u8, begin - stat.arg_start) };
+    if slots.last() != Some(&0) {
+        return begin_addr;
+    }
+    debug!(
+        "argv starts {} bytes into the kernel area, including the loader slots: {:?}",
+        slots.len(),
+        OsStr::from_bytes(slots)
+    );
+    stat.arg_start as *const c_char
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn with_loader_slots(begin_addr: *const c_char, _end_addr: *const c_char) -> *const c_char {
+    begin_addr
+}
+
+// libbsd, PostgreSQL and python-setproctitle copy every variable elsewhere and point
+// environ at the copies before writing titles over the original area, libbsd and
+// python-setproctitle move argv[1] and later to copies as well.
+//
+// Once we moved environ ourselves, the program setting its variables again,
+// e.g. clearenv() and setenv() of the same ones, copies it just the same,
+// only argv elements that are not our copies tell a takeover apart then.
+fn taken_over(argv_mem: &MemInfo, env_mem: Option<&MemInfo>) -> bool {
+    let relocated = RELOCATED.lock().unwrap_or_else(PoisonError::into_inner);
+    let argv_moved = argv_mem.count > 1
+        && (1..argv_mem.count)
+            .map(|i| unsafe { argv_mem.ptr.add(i).read() })
+            .all(|ptr| {
+                !ptr.is_null()
+                    && !(argv_mem.begin_addr..=argv_mem.end_addr).contains(&ptr)
+                    && !relocated.argv.contains(&(ptr as usize))
+            });
+    let environ_moved = match env_mem {
+        Some(env_mem) if relocated.environ.is_empty() => environ_copied(env_mem),
+        Some(_) => {
+            debug!("environ was relocated by an earlier handle");
+            false
+        }
+        None => false,
+    };
+    debug!("argv moved: {argv_moved}, environ moved: {environ_moved}");
+    argv_moved || environ_moved
+}
+
+// Only setenv and clearenv leave some of environ in use or the content changed.
+fn environ_copied(env_mem: &MemInfo) -> bool {
+    let Some(current) = (unsafe { env_addr::envptr() }) else {
+        return false;
+    };
+    let mut current_strs = HashSet::new();
+    for i in 0.. {
+        let ptr = unsafe { current.add(i).read() };
+        if ptr.is_null() {
+            break;
+        }
+        if (env_mem.begin_addr..=env_mem.end_addr).contains(&ptr) {
+            return false;
+        }
+        current_strs.insert(unsafe { CStr::from_ptr(ptr) });
+    }
+    let taken_over = (0..env_mem.count)
+        .map(|i| unsafe { CStr::from_ptr(env_mem.ptr.add(i).read()) })
+        .all(|original| current_strs.contains(original));
+    trace!("environ copied: {taken_over}");
+    taken_over
+}
+
+// The strings from begin up to argv[0], only there with_loader_slots() moved begin.
+fn save_slots(begin: *const c_char, argv0: *const c_char) -> Vec<CString> {
+    let mut saved = Vec::new();
+    let mut ptr = begin;
+    while ptr < argv0 {
+        let slot = unsafe { CStr::from_ptr(ptr) };
+        ptr = unsafe { ptr.add(slot.to_bytes_with_nul().len()) };
+        saved.push(slot.into());
+    }
+    saved
+}
+
 // The expected input is always the checked output of from_addr()
 fn save_string(count: usize, ptr: *const *const c_char) -> Vec<CString> {
     let mut saved: Vec<CString> = Vec::with_capacity(count);
     let cstr_ptrs = unsafe { slice::from_raw_parts(ptr, count) };
-    for (i, cstr_ptr) in cstr_ptrs.into_iter().enumerate() {
+    for (i, cstr_ptr) in cstr_ptrs.iter().enumerate() {
         trace!("string[{i}]={cstr_ptr:?}, ptr={:?}", cstr_ptr as *const _);
         if cstr_ptr.is_null() {
             warn!("the string[{i}] is null, pls check");
@@ -148,9 +394,9 @@ fn save_string(count: usize, ptr: *const *const c_char) -> Vec<CString> {
 }
 
 // Get environ address, ignore errors
-fn from_env() -> Option<MemInfo> {
-    let (count, ptr) = env_addr::addr()?;
-    unsafe { from_addr(count, ptr) }
+fn from_env(addr: Option<(usize, *const *const c_char)>) -> Option<MemInfo> {
+    let (count, ptr) = addr?;
+    unsafe { from_addr("environ", count, ptr) }
         .map_err(|e| {
             trace!("env err: {e:?}");
             e
@@ -158,31 +404,100 @@ fn from_env() -> Option<MemInfo> {
         .ok()
 }
 
-fn from_argv() -> Result<MemInfo, EnvError> {
+// The kernel knows better than the pointers where the region it renders is.
+// Its areas are used as long as the strings found start them and stay within them,
+// strings no longer reachable from the pointers are part of the region then.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn procfs_region(
+    argv_mem: MemInfo,
+    env_mem: Option<MemInfo>,
+) -> Result<(MemInfo, Option<MemInfo>), EnvError> {
+    let stat = procfs::stat().ok_or(EnvError::ProcfsUnavailable)?;
+    // end_addr is the terminating NUL byte, the kernel points one past it.
+    let area = |area, mem: MemInfo, start: usize, end: usize| {
+        let found = (mem.begin_addr as usize, mem.end_addr as usize + 1);
+        debug!(
+            "procfs {area} area: {start:#x} -> {end:#x}, found: {:#x} -> {:#x}",
+            found.0, found.1
+        );
+        if found.0 == start && found.1 <= end {
+            Ok(MemInfo {
+                end_addr: (end - 1) as *const c_char,
+                ..mem
+            })
+        } else {
+            Err(EnvError::ProcfsMismatch {
+                area,
+                start,
+                end,
+                found_start: found.0,
+                found_end: found.1,
+            })
+        }
+    };
+    let argv_mem = area("argv", argv_mem, stat.arg_start, stat.arg_end)?;
+    let env_mem = env_mem
+        .map(|env_mem| area("environ", env_mem, stat.env_start, stat.env_end))
+        .transpose()?;
+    Ok((argv_mem, env_mem))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn procfs_region(
+    _argv_mem: MemInfo,
+    _env_mem: Option<MemInfo>,
+) -> Result<(MemInfo, Option<MemInfo>), EnvError> {
+    Err(EnvError::ProcfsUnavailable)
+}
+
+// Try each source in turn, the first one passing from_addr() wins.
+fn find_argv(sources: &[Arc<dyn ArgvSource>]) -> Result<(MemInfo, &'static str), EnvError> {
+    let mut last_err = EnvError::NoArgvSource;
+    for source in sources {
+        let found = source
+            .find()
+            .and_then(|(count, ptr)| unsafe { from_addr("argv", count, ptr) });
+        match found {
+            Ok(argv_info) => {
+                debug!("argv found by source {}", source.name());
+                return Ok((argv_info, source.name()));
+            }
+            Err(e) => {
+                debug!("argv source {} failed: {e}", source.name());
+                // Says more than whatever the sources after it fail with.
+                if !matches!(last_err, EnvError::EmptyArgv) {
+                    last_err = e;
+                }
+            }
+        }
+    }
+    Err(last_err)
+}
+
+fn from_argv(sources: &[Arc<dyn ArgvSource>]) -> Result<(MemInfo, &'static str), EnvError> {
     let argv_info = match ARGV_MEM.get() {
         None => {
-            let (count, ptr) = argv_addr::addr()?;
-            let argv_info = unsafe { from_addr(count, ptr) }.map_err(|e| {
-                trace!("argv err: {e:?}");
-                e
-            })?;
-            ARGV_MEM.get_or_init(|| Mutex::new(argv_info))
+            let argv_info = find_argv(sources)?;
+            ARGV_MEM.get_or_init(|| {
+                fork::register();
+                Mutex::new(argv_info)
+            })
         }
         Some(val) => val,
     };
-    Ok(*argv_info.lock().unwrap())
+    Ok(argv_info.lock().unwrap().clone())
 }
 
 /// Get the argv start address and end address.
 pub fn argv_addrs() -> Result<(*mut u8, *mut u8), EnvError> {
-    from_argv().map(|m| (m.begin_addr as *mut u8, m.end_addr as *mut u8))
+    from_argv(&default_sources()).map(|(m, _)| (m.begin_addr as *mut u8, m.end_addr as *mut u8))
 }
 
 /// Get raw args count and args pointer
 /// # Safety
 /// The string address is changed after KillMyArgv::new() with the replace_argv_element feature enabled.
 pub unsafe fn argc_argv() -> Result<(usize, *const *const c_char), EnvError> {
-    argv_addr::addr()
+    find_argv(&default_sources()).map(|(m, _)| (m.count, m.ptr))
 }
 
 /// Get raw environ pointer
@@ -198,21 +513,208 @@ impl KillMyArgv {
         (self.begin_addr, self.end_addr)
     }
 
+    /// Initialize with the default options, see [`Builder`].
     pub fn new() -> Result<KillMyArgv, EnvError> {
+        Builder::new().build()
+    }
+
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    /// Initialize from the argc/argv/envp given to a C `main`,
+    /// without looking for them anywhere else.
+    ///
+    /// This is meant for embedders, e.g. a C program calling into a Rust staticlib.
+    /// Once argv was found, by an earlier handle or by this, a different `argv`
+    /// fails with [`EnvError::ArgvMismatch`].
+    /// # Safety
+    /// `argv` and `envp` must be the unmodified arrays handed to the process by the OS,
+    /// and `argv` must hold at least `argc` elements.
+    pub unsafe fn from_raw_parts(
+        argc: c_int,
+        argv: *const *const c_char,
+        envp: *const *const c_char,
+    ) -> Result<KillMyArgv, EnvError> {
+        Builder::new().build_from_raw_parts(argc, argv, envp)
+    }
+
+    fn from_builder(builder: &Builder) -> Result<KillMyArgv, EnvError> {
         debug!("current target: {}", env!("TARGET"));
-        let argv_mem = from_argv()?;
-        let argv_saved = save_string(argv_mem.count, argv_mem.ptr);
+        let sources = if builder.sources.is_empty() {
+            default_sources()
+        } else {
+            builder.sources.clone()
+        };
+        let (argv_mem, source) = from_argv(&sources)?;
+        Self::from_mem(builder, argv_mem, source, env_addr::addr)
+    }
+
+    // What a handle with the default options finds, without touching anything
+    // nor remembering argv for later.
+    fn dry_run() -> Result<KillMyArgv, EnvError> {
+        let builder = Builder {
+            dry_run: true,
+            ..Builder::new()
+        };
+        let (argv_mem, source) = match ARGV_MEM.get() {
+            Some(argv_info) => argv_info.lock().unwrap().clone(),
+            None => find_argv(&default_sources())?,
+        };
+        Self::from_mem(&builder, argv_mem, source, env_addr::addr)
+    }
+
+    unsafe fn from_builder_raw_parts(
+        builder: &Builder,
+        argc: c_int,
+        argv: *const *const c_char,
+        envp: *const *const c_char,
+    ) -> Result<KillMyArgv, EnvError> {
+        debug!(
+            "current target: {}, raw parts: argc={argc}, argv={argv:?}, envp={envp:?}",
+            env!("TARGET")
+        );
+        let argv_mem = from_addr("argv", argc.max(0) as usize, argv).map_err(|e| {
+            trace!("argv err: {e:?}");
+            e
+        })?;
+        // Keep argc_argv(), argv_addrs() and later handles in line with what we were given,
+        // unless argv was already found, the region is not changed under them.
+        let found = ARGV_MEM.get_or_init(|| {
+            argv_addr::init(argc as isize, argv.cast());
+            argv_addr::init_envp(envp);
+            fork::register();
+            Mutex::new((argv_mem.clone(), "raw_parts"))
+        });
+        let (found_mem, found_by) = found.lock().unwrap().clone();
+        if found_mem.ptr != argv_mem.ptr {
+            return Err(EnvError::ArgvMismatch {
+                ptr: argv,
+                found_by,
+            });
+        }
+        Self::from_mem(builder, argv_mem, "raw_parts", || env_addr::addr_from(envp))
+    }
+
+    fn from_mem(
+        builder: &Builder,
+        argv_mem: MemInfo,
+        source: &'static str,
+        env_addr: impl FnOnce() -> Option<(usize, *const *const c_char)>,
+    ) -> Result<KillMyArgv, EnvError> {
+        let mut argv_saved = save_slots(argv_mem.begin_addr, unsafe { argv_mem.ptr.read() });
+        argv_saved.extend(save_string(argv_mem.count, argv_mem.ptr));
+        let env_found = from_env(env_addr());
+        let padded = std::env::var_os(PADDING_VAR).is_some();
+        let args = argv_saved[argv_saved.len() - argv_mem.count..]
+            .iter()
+            .map(|arg| arg.to_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let prefix = title_prefix(builder.title_prefix, &args[0]);
+        let cooperate = taken_over(&argv_mem, env_found.as_ref());
+        if cooperate {
+            if !builder.cooperative {
+                return Err(EnvError::RegionTakenOver);
+            }
+            warn!("argv and environ were taken over, only writing into their region");
+        }
+        let argv_mem = match &env_found {
+            // The region of the other implementation runs from argv[0] to the end of environ,
+            // whatever argv elements point to now.
+            Some(env_mem) if cooperate && env_mem.begin_addr > argv_mem.end_addr => MemInfo {
+                end_addr: env_mem.begin_addr.wrapping_sub(1),
+                ..argv_mem
+            },
+            _ => argv_mem,
+        };
+        // Nothing has been touched yet, bail out before that.
+        let (argv_mem, env_found) = if builder.procfs_region {
+            procfs_region(argv_mem, env_found)?
+        } else {
+            (argv_mem, env_found)
+        };
         // It can be replaced by std::ptr::sub_ptr() in the future.
         let argv_len = unsafe { argv_mem.end_addr.offset_from(argv_mem.begin_addr) as usize };
 
         trace!("argv struct: {argv_mem:#?}, saved: {argv_saved:#?}, len={argv_len}");
-        if cfg!(feature = "replace_argv_element") {
+        // environ is never touched without the clobber_environ feature.
+        // Not touched when cooperating, only written to.
+        let use_environ = cooperate
+            || match threads::count().filter(|_| cfg!(feature = "clobber_environ")) {
+                Some(threads) if threads > 1 => match builder.thread_policy {
+                    ThreadPolicy::ArgvOnly => {
+                        warn!("{threads} threads are running, environ will not be clobbered");
+                        false
+                    }
+                    ThreadPolicy::Error => return Err(EnvError::MultiThreaded { threads }),
+                    ThreadPolicy::Ignore => {
+                        warn!("{threads} threads are running, clobber environ anyway");
+                        true
+                    }
+                },
+                _ => true,
+            };
+        let env_mem = if use_environ && argv_len <= OS_MAX_LEN_LIMIT {
+            // Only when it directly follows argv, what is in between is not known to be ours.
+            env_found.clone().filter(|env_mem| {
+                let adjacent = env_mem.begin_addr == argv_mem.end_addr.wrapping_add(1);
+                if !adjacent {
+                    warn!(
+                        "environ {:?} does not follow argv {:?}, it will not be used",
+                        env_mem.begin_addr, argv_mem.end_addr
+                    );
+                }
+                adjacent
+            })
+        } else {
+            None
+        };
+        let mut strays = argv_mem.strays.clone();
+        if let Some(env_mem) = &env_mem {
+            strays.extend_from_slice(&env_mem.strays);
+        }
+        let env_len = env_mem
+            .as_ref()
+            .map(|env_mem| unsafe { env_mem.end_addr.offset_from(env_mem.begin_addr) as usize });
+        let max_len = match env_len {
+            Some(env_len) => cmp::min(argv_len + 1 + env_len, OS_MAX_LEN_LIMIT),
+            None if cfg!(any(target_os = "illumos", target_os = "solaris")) => {
+                cmp::min(argv_len, OS_MAX_LEN_LIMIT)
+            }
+            None => argv_len,
+        };
+        // A prefix kept as an element of its own has to leave room for a title in argv.
+        if prefix.ends_with(b"\0") && prefix.len() >= cmp::min(argv_len, max_len) {
+            return Err(EnvError::InsufficientCapacity {
+                needed: 1,
+                max_len: 0,
+            });
+        }
+        // The original pointers are read, so before argv elements are replaced.
+        let layout = Layout::new(
+            Region {
+                start: argv_mem.begin_addr as *const u8,
+                len: max_len,
+            },
+            &argv_mem,
+            env_mem.as_ref(),
+        );
+        trace!("layout: {layout:#?}");
+        let saved_region =
+            unsafe { slice::from_raw_parts(argv_mem.begin_addr as *const u8, max_len) }.to_vec();
+        let last_written = Cell::new(checksum(&saved_region));
+        if cfg!(feature = "replace_argv_element") && !cooperate && !builder.dry_run {
             let mut new_argvp = argv_saved
                 .clone()
                 .leak()
                 .iter()
                 .map(|s| s.as_ptr())
                 .collect::<Vec<*const c_char>>();
+            RELOCATED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .argv
+                .extend(new_argvp.iter().map(|&ptr| ptr as usize));
 
             for i in (0..argv_mem.count).rev() {
                 if let Some(new_ptr) = new_argvp.pop() {
@@ -230,74 +732,380 @@ impl KillMyArgv {
                 }
             }
         }
-        if argv_len - 1 < OS_MAX_LEN_LIMIT {
-            if let Some(env_mem) = from_env() {
-                let env_saved = save_string(env_mem.count, env_mem.ptr);
-                let env_len = unsafe { env_mem.end_addr.offset_from(env_mem.begin_addr) as usize };
-                trace!("env struct: {env_mem:#?}, saved: {env_saved:#?}, len={env_len}");
-                #[allow(unused)]
-                #[cfg(all(feature = "clobber_environ", feature = "replace_environ_element"))]
-                // I haven't decided if I want to remove it or not,
-                // since setenv makes it probably unnecessary.
-                let mut new_envp = env_saved
-                    .iter()
-                    .map(|s| s.as_ptr())
-                    .collect::<Vec<*const c_char>>();
-
-                // Using std instead of manually replacing each element in environ
-                // is just being lazy.
-                #[cfg(all(feature = "clobber_environ", feature = "replace_environ_element"))]
+        if let Some(env_mem) = env_mem {
+            let env_saved = save_string(env_mem.count, env_mem.ptr);
+            trace!("env struct: {env_mem:#?}, saved: {env_saved:#?}, len={env_len:?}");
+            #[allow(unused)]
+            #[cfg(all(feature = "clobber_environ", feature = "replace_environ_element"))]
+            // I haven't decided if I want to remove it or not,
+            // since setenv makes it probably unnecessary.
+            let mut new_envp = env_saved
+                .iter()
+                .map(|s| s.as_ptr())
+                .collect::<Vec<*const c_char>>();
+
+            // Using std instead of manually replacing each element in environ
+            // is just being lazy.
+            #[cfg(all(feature = "clobber_environ", feature = "replace_environ_element"))]
+            if !cooperate && !builder.dry_run {
                 for (key, value) in vars_os() {
                     remove_var(&key);
-                    set_var(key, value); // Expected: libc::setenv(key, value, 1)
+                    // Its bytes stay in the region, the program does not get to see it.
+                    if key != PADDING_VAR {
+                        set_var(key, value); // Expected: libc::setenv(key, value, 1)
+                    }
+                }
+                if let Some(envp) = unsafe { env_addr::envptr() } {
+                    let copies = (0..)
+                        .map(|i| unsafe { envp.add(i).read() })
+                        .take_while(|ptr| !ptr.is_null())
+                        .map(|ptr| ptr as usize);
+                    RELOCATED
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .environ
+                        .extend(copies);
                 }
-
-                return Ok(KillMyArgv {
-                    begin_addr: argv_mem.begin_addr as *mut u8,
-                    end_addr: env_mem.end_addr as *mut u8,
-                    max_len: cmp::min(argv_len + 1 + env_len, OS_MAX_LEN_LIMIT),
-                    saved_argv: argv_saved,
-                    nonul_byte: Some(argv_len),
-                });
             }
+            forget_padding(padded && !builder.dry_run);
+
+            return Ok(KillMyArgv {
+                begin_addr: argv_mem.begin_addr as *mut u8,
+                end_addr: env_mem.end_addr as *mut u8,
+                max_len,
+                saved_argv: argv_saved,
+                nonul_byte: Some(argv_len + 1),
+                source,
+                strays,
+                layout,
+                saved_region,
+                argv_ptr: argv_mem.ptr,
+                revert_on_drop: builder.revert_on_drop,
+                minimal_clobber: builder.minimal_clobber,
+                modified_policy: builder.modified_policy,
+                last_written,
+                padded,
+                paddable: true,
+                prefix,
+                args: RefCell::new(args),
+                arg_overflow: builder.arg_overflow,
+            });
         }
+        forget_padding(padded && !builder.dry_run);
         Ok(KillMyArgv {
             begin_addr: argv_mem.begin_addr as *mut u8,
             end_addr: argv_mem.end_addr as *mut u8,
-            max_len: if cfg!(any(target_os = "illumos", target_os = "solaris")) {
-                cmp::min(argv_len, OS_MAX_LEN_LIMIT)
-            } else {
-                argv_len
-            },
+            max_len,
             saved_argv: argv_saved,
             nonul_byte: None,
+            source,
+            strays,
+            layout,
+            saved_region,
+            argv_ptr: argv_mem.ptr,
+            revert_on_drop: builder.revert_on_drop,
+            minimal_clobber: builder.minimal_clobber,
+            modified_policy: builder.modified_policy,
+            last_written,
+            padded,
+            paddable: use_environ && env_found.is_none(),
+            prefix,
+            args: RefCell::new(args),
+            arg_overflow: builder.arg_overflow,
         })
     }
 
+    /// Name of the [`ArgvSource`] argv was found with,
+    /// `raw_parts` for [`KillMyArgv::from_raw_parts`].
+    pub fn source(&self) -> &'static str {
+        self.source
+    }
+
+    /// Where the argv and environ strings were found at initialization.
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// argv and environ elements found pointing outside of the region,
+    /// they are left alone and are not part of [`KillMyArgv::max_len`].
+    pub fn stray_pointers(&self) -> &[StrayPointer] {
+        &self.strays
+    }
+
     /// Gets the maximum byte length for which the cmdline can be set.
     pub fn max_len(&self) -> usize {
-        self.max_len
+        // A prefix kept as an element of its own keeps the title within argv.
+        let max_len = if self.prefix.ends_with(b"\0") {
+            self.argv_area_len()
+        } else {
+            self.max_len
+        };
+        max_len.saturating_sub(self.prefix.len())
+    }
+
+    // Once the cmdline runs into environ the kernel only shows its first element,
+    // so multiple elements have to stay within argv, without its last NUL byte.
+    fn argv_area_len(&self) -> usize {
+        match self.nonul_byte {
+            Some(nonul_byte) => cmp::min(self.max_len, nonul_byte - 1),
+            None => self.max_len,
+        }
+    }
+
+    // argv as found, without the loader slots in front of it.
+    fn saved_args(&self) -> &[CString] {
+        &self.saved_argv[self.saved_argv.len() - self.layout.argv_entries.len()..]
+    }
+
+    /// Make sure titles of `n` bytes fit, by re-executing the program with
+    /// a padding variable added to its environment when they do not.
+    ///
+    /// The program starts over with the same argv, call this early in `main`.
+    /// After the restart the padding is part of the region, and removed from
+    /// the environment during initialization. This call then returns `Ok`,
+    /// or fails with [`EnvError::InsufficientCapacity`] instead of re-executing again.
+    /// Only environ can be padded, so it needs the `clobber_environ` feature,
+    /// and fails right away when environ is kept out of the region
+    /// or the title prefix keeps titles within argv.
+    pub fn ensure_capacity(&self, n: usize) -> Result<(), EnvError> {
+        let max_len = self.max_len();
+        if n <= max_len {
+            return Ok(());
+        }
+        let insufficient = EnvError::InsufficientCapacity { needed: n, max_len };
+        if self.padded
+            || !cfg!(feature = "clobber_environ")
+            || n > OS_MAX_LEN_LIMIT
+            || !self.paddable
+            || self.prefix.ends_with(b"\0")
+        {
+            return Err(insufficient);
+        }
+        let exe = if cfg!(any(target_os = "linux", target_os = "android")) {
+            PathBuf::from("/proc/self/exe")
+        } else {
+            std::env::current_exe().map_err(|_| insufficient)?
+        };
+        let padding = n - max_len;
+        // With the loader slots, /proc/self/exe is the loader when it started the program.
+        let argv = &self.saved_argv;
+        let mut command = Command::new(exe);
+        if let Some(arg0) = argv.first() {
+            command.arg0(OsStr::from_bytes(arg0.to_bytes()));
+        }
+        command
+            .args(
+                argv.iter()
+                    .skip(1)
+                    .map(|arg| OsStr::from_bytes(arg.to_bytes())),
+            )
+            .env(PADDING_VAR, "x".repeat(padding));
+        debug!("re-executing with {padding} bytes of padding");
+        let e = command.exec();
+        Err(EnvError::ReexecFailed {
+            errno: e.raw_os_error().unwrap_or(0),
+        })
     }
 
     /// origin argv length, containing the terminating NUL byte.
     /// This bit is written to a non-nul value requiring attention to os behavior.
+    ///
+    /// Earlier versions returned one less, the length without the terminating NUL byte.
+    /// `None` when environ is not part of the region.
     pub fn nonul_byte(&self) -> Option<usize> {
         self.nonul_byte
     }
 
     /// Undo the args/cmdline changes.
     pub fn revert(&self) {
+        if let Err(e) = self.try_revert() {
+            error!("{e}");
+        }
+    }
+
+    /// Like [`KillMyArgv::revert`], failing with [`EnvError::RegionModified`]
+    /// under [`ModifiedPolicy::Error`].
+    pub fn try_revert(&self) -> Result<(), EnvError> {
         let backup_chars: Vec<u8> = self
             .saved_argv
             .iter()
             .flat_map(|s| s.as_bytes_with_nul())
             .cloned()
             .collect();
-        Self::set(self, &backup_chars)
+        self.write(&backup_chars)?;
+        self.reset_args();
+        Ok(())
+    }
+
+    fn reset_args(&self) {
+        *self.args.borrow_mut() = self
+            .saved_args()
+            .iter()
+            .map(|arg| arg.to_bytes().to_vec())
+            .collect();
+    }
+
+    /// Whether the region changed since it was last written by this handle,
+    /// or since initialization when nothing was written yet.
+    ///
+    /// Some other code wrote into argv or environ then, e.g. a C library or
+    /// another setproctitle implementation.
+    pub fn has_been_modified(&self) -> bool {
+        self.region_checksum() != self.last_written.get()
+    }
+
+    fn region_checksum(&self) -> u64 {
+        checksum(unsafe { slice::from_raw_parts(self.begin_addr, self.max_len) })
+    }
+
+    /// Undo every change since initialization, the environ part of the region included,
+    /// and point the argv elements back into the region.
+    ///
+    /// Afterwards `/proc/<pid>/cmdline` and `/proc/<pid>/environ` read as they did at startup,
+    /// environ itself keeps pointing at the copies made at initialization.
+    /// A later [`KillMyArgv::set`] overwrites what argv points to again.
+    pub fn revert_full(&self) {
+        if let Err(e) = self.try_revert_full() {
+            error!("{e}");
+        }
+    }
+
+    /// Like [`KillMyArgv::revert_full`], failing with [`EnvError::RegionModified`]
+    /// under [`ModifiedPolicy::Error`].
+    pub fn try_revert_full(&self) -> Result<(), EnvError> {
+        self.check_modified()?;
+        trace!("full revert of {} bytes", self.saved_region.len());
+        unsafe {
+            slice::from_raw_parts_mut(self.begin_addr, self.saved_region.len())
+                .copy_from_slice(&self.saved_region);
+            // Strays were left alone at initialization.
+            for entry in self
+                .layout
+                .argv_entries
+                .iter()
+                .filter(|e| e.offset.is_some())
+            {
+                (self.argv_ptr.add(entry.index) as *mut *const c_char).write(entry.ptr);
+            }
+        }
+        self.last_written.set(checksum(&self.saved_region));
+        self.reset_args();
+        Ok(())
     }
 
     /// set a new args/cmdline.
     pub fn set(&self, chars: &[u8]) {
+        if let Err(e) = self.try_set(chars) {
+            error!("{e}");
+        }
+    }
+
+    /// Like [`KillMyArgv::set`], failing with [`EnvError::RegionModified`]
+    /// under [`ModifiedPolicy::Error`] when the region changed since the last write.
+    pub fn try_set(&self, chars: &[u8]) -> Result<(), EnvError> {
+        let chars = if self.prefix.is_empty() {
+            chars.to_vec()
+        } else {
+            let chars = &chars[..cmp::min(chars.len(), self.max_len())];
+            [self.prefix.as_slice(), chars].concat()
+        };
+        self.write(&chars)?;
+        *self.args.borrow_mut() = self.written_args(chars.len());
+        Ok(())
+    }
+
+    // The arguments as shown after writing `len` bytes, split from the region
+    // itself, as what did not fit was cut off.
+    fn written_args(&self, len: usize) -> Vec<Vec<u8>> {
+        let written =
+            unsafe { slice::from_raw_parts(self.begin_addr, cmp::min(len, self.max_len)) };
+        let title = written.strip_suffix(b"\0").unwrap_or(written);
+        title.split(|&b| b == 0).map(<[u8]>::to_vec).collect()
+    }
+
+    /// Replace argument `i` of the cmdline, argv[0] being argument 0.
+    ///
+    /// The arguments are those shown since the last [`KillMyArgv::set`], split at their NUL bytes,
+    /// or argv as found. They have to fit into the argv area, see [`ArgOverflow`].
+    /// `std::env::args` is not affected with the `replace_argv_element` feature.
+    pub fn set_arg(&self, i: usize, value: &[u8]) -> Result<(), EnvError> {
+        self.edit_args(|args| {
+            let len = args.len();
+            *args
+                .get_mut(i)
+                .ok_or(EnvError::ArgIndexOutOfRange { index: i, len })? = arg(value)?;
+            Ok(())
+        })
+    }
+
+    /// Remove argument `i` from the cmdline, see [`KillMyArgv::set_arg`].
+    pub fn remove_arg(&self, i: usize) -> Result<(), EnvError> {
+        self.edit_args(|args| {
+            if i >= args.len() {
+                return Err(EnvError::ArgIndexOutOfRange {
+                    index: i,
+                    len: args.len(),
+                });
+            }
+            args.remove(i);
+            Ok(())
+        })
+    }
+
+    /// Append an argument to the cmdline, see [`KillMyArgv::set_arg`].
+    pub fn push_arg(&self, value: &[u8]) -> Result<(), EnvError> {
+        self.edit_args(|args| {
+            args.push(arg(value)?);
+            Ok(())
+        })
+    }
+
+    fn edit_args(
+        &self,
+        edit: impl FnOnce(&mut Vec<Vec<u8>>) -> Result<(), EnvError>,
+    ) -> Result<(), EnvError> {
+        let mut args = self.args.borrow().clone();
+        edit(&mut args)?;
+        let mut chars = args.join(&0);
+        let max_len = self.argv_area_len();
+        let truncated = chars.len() > max_len;
+        if truncated {
+            match self.arg_overflow {
+                ArgOverflow::Error => {
+                    return Err(EnvError::InsufficientCapacity {
+                        needed: chars.len(),
+                        max_len,
+                    })
+                }
+                ArgOverflow::Truncate => chars.truncate(max_len),
+            }
+        }
+        self.write(&chars)?;
+        *self.args.borrow_mut() = if truncated {
+            self.written_args(chars.len())
+        } else {
+            args
+        };
+        Ok(())
+    }
+
+    fn check_modified(&self) -> Result<(), EnvError> {
+        if self.has_been_modified() {
+            match self.modified_policy {
+                ModifiedPolicy::Warn => {
+                    warn!("The region was modified by someone else, overwriting it.")
+                }
+                ModifiedPolicy::Error => return Err(EnvError::RegionModified),
+                ModifiedPolicy::Overwrite => {
+                    debug!("The region was modified by someone else, overwriting it.")
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, chars: &[u8]) -> Result<(), EnvError> {
+        self.check_modified()?;
         trace!(
             "set len: {:?}, need not null byte: {:?}, String: {:?}, bytes hex: {chars:02x?}",
             chars.len(),
@@ -308,9 +1116,18 @@ impl KillMyArgv {
             if chars.len() < self.max_len {
                 slice::from_raw_parts_mut(self.begin_addr, chars.len()).copy_from_slice(chars);
 
+                // In minimal clobber mode the environ bytes past the title are put back.
+                let fill_end = match self.nonul_byte {
+                    Some(nonul_byte) if self.minimal_clobber => {
+                        cmp::min(cmp::max(chars.len() + 1, nonul_byte), self.max_len)
+                    }
+                    _ => self.max_len,
+                };
                 self.begin_addr
                     .add(chars.len())
-                    .write_bytes(0x00, self.max_len - chars.len());
+                    .write_bytes(0x00, fill_end - chars.len());
+                slice::from_raw_parts_mut(self.begin_addr.add(fill_end), self.max_len - fill_end)
+                    .copy_from_slice(&self.saved_region[fill_end..]);
             } else {
                 slice::from_raw_parts_mut(self.begin_addr, self.max_len)
                     .copy_from_slice(&chars[..self.max_len]);
@@ -318,6 +1135,8 @@ impl KillMyArgv {
             // It should be handled by advanced packaging or users,
             // and is difficultto dispose of properly here.
             if let Some(nonul_byte) = self.nonul_byte {
+                // The terminating NUL byte of argv, the kernel reads on into environ
+                // only when it is overwritten.
                 if chars.len() > nonul_byte && self.begin_addr.add(nonul_byte - 1).read() == 0x00 {
                     warn!(
                         "Note! you try in nonul byte({nonul_byte}) write null, {}, {}",
@@ -332,5 +1151,62 @@ impl KillMyArgv {
                 error!("BUG! Unexpected non-null value: {end:?}");
             }
         }
+        self.last_written.set(self.region_checksum());
+        Ok(())
+    }
+}
+
+fn arg(value: &[u8]) -> Result<Vec<u8>, EnvError> {
+    if value.contains(&0) {
+        return Err(EnvError::NulInArg);
+    }
+    Ok(value.to_vec())
+}
+
+fn title_prefix(prefix: TitlePrefix, argv0: &[u8]) -> Vec<u8> {
+    let basename = argv0.rsplit(|&b| b == b'/').next().unwrap_or(argv0);
+    match prefix {
+        TitlePrefix::None => Vec::new(),
+        TitlePrefix::Argv0 => [argv0, b"\0"].concat(),
+        TitlePrefix::Basename => [basename, b"\0"].concat(),
+        TitlePrefix::Progname => [basename, b": "].concat(),
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+// The padding from ensure_capacity() is never seen by the program, whether
+// environ was relocated or not. Only once its array has been read.
+fn forget_padding(padded: bool) {
+    if padded {
+        std::env::remove_var(PADDING_VAR);
+    }
+}
+
+impl Drop for KillMyArgv {
+    fn drop(&mut self) {
+        if self.revert_on_drop {
+            self.revert_full();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_addr_empty_argv() {
+        // What the kernel passes for execve(path, {NULL}, envp).
+        let argv: [*const c_char; 1] = [std::ptr::null()];
+        let result = unsafe { from_addr("argv", 0, argv.as_ptr()) };
+        assert!(matches!(result, Err(EnvError::EmptyArgv)), "{result:?}");
+
+        let environ = unsafe { from_addr("environ", 0, argv.as_ptr()) };
+        assert!(matches!(environ, Err(EnvError::PonitToNull { .. })));
     }
 }
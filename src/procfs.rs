@@ -0,0 +1,60 @@
+use std::fs;
+
+use log::trace;
+
+/// The fields of `/proc/self/stat` describing where the kernel put argv and environ.
+///
+/// See proc_pid_stat(5), they are there since Linux 3.5.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Stat {
+    pub arg_start: usize,
+    pub arg_end: usize,
+    pub env_start: usize,
+    pub env_end: usize,
+}
+
+pub(super) fn stat() -> Option<Stat> {
+    let stat = fs::read_to_string("/proc/self/stat")
+        .map_err(|e| trace!("failed to read /proc/self/stat: {e}"))
+        .ok()?;
+    parse_stat(&stat)
+}
+
+fn parse_stat(stat: &str) -> Option<Stat> {
+    // comm can hold spaces and parentheses, the fields start after the last ')'
+    // and are counted from 1, with pid and comm being the first two.
+    let fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let field = |n: usize| fields.get(n - 3)?.parse::<usize>().ok();
+    let stat = Stat {
+        arg_start: field(48)?,
+        arg_end: field(49)?,
+        env_start: field(50)?,
+        env_end: field(51)?,
+    };
+    trace!("procfs stat: {stat:x?}");
+    // Zero when we are not allowed to see them.
+    if stat.arg_start == 0 {
+        None
+    } else {
+        Some(stat)
+    }
+}
+
+/// The `[start, end)` of the mapping in `/proc/self/maps` holding `addr`.
+pub(super) fn mapping(addr: usize) -> Option<(usize, usize)> {
+    let maps = fs::read_to_string("/proc/self/maps")
+        .map_err(|e| trace!("failed to read /proc/self/maps: {e}"))
+        .ok()?;
+    maps.lines().find_map(|line| {
+        let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+        let start = usize::from_str_radix(start, 16).ok()?;
+        let end = usize::from_str_radix(end, 16).ok()?;
+        (start..end).contains(&addr).then(|| {
+            trace!("{addr:#x} is in mapping: {line}");
+            (start, end)
+        })
+    })
+}
@@ -0,0 +1,161 @@
+use super::{argv_addr, stack_walk, EnvError};
+use std::{ffi::c_char, fmt::Debug, sync::Arc};
+
+/// A way of finding argc and the argv array of the current process.
+///
+/// Sources are tried in order by [`Builder`](crate::Builder), the first one
+/// whose result passes validation is used.
+///
+/// # Safety
+/// The pointer returned by [`ArgvSource::find`] is dereferenced: on success it has to
+/// point to an array of at least `count` valid C strings, terminated by a NULL pointer,
+/// which stays valid for the rest of the process.
+pub unsafe trait ArgvSource: Debug + Send + Sync {
+    /// Recorded in [`KillMyArgv::source`](crate::KillMyArgv::source) when this source is used.
+    fn name(&self) -> &'static str;
+
+    /// Returns argc and the argv array.
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError>;
+}
+
+/// The argc/argv captured in .init_array, or given by the OS on Apple platforms.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InitArray;
+
+unsafe impl ArgvSource for InitArray {
+    fn name(&self) -> &'static str {
+        "init_array"
+    }
+
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError> {
+        argv_addr::from_imp()
+    }
+}
+
+/// Computes argv back from environ, using the argc seen by `std::env::args`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Computed;
+
+unsafe impl ArgvSource for Computed {
+    fn name(&self) -> &'static str {
+        "computed"
+    }
+
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError> {
+        argv_addr::comp_argv()
+    }
+}
+
+/// Walks the initial stack backwards from environ until it finds argc.
+///
+/// The walk stays inside the stack mapping, checks every argv element on
+/// the way and gives up after `max_argc` slots. Where the mapping is unknown
+/// (everywhere but Linux) it fails without reading anything.
+#[derive(Clone, Copy, Debug)]
+pub struct StackWalking {
+    max_argc: usize,
+}
+
+impl StackWalking {
+    pub fn new() -> StackWalking {
+        StackWalking {
+            max_argc: stack_walk::DEFAULT_MAX_ARGC,
+        }
+    }
+
+    /// Give up when argc would be larger than this.
+    pub fn max_argc(mut self, max_argc: usize) -> StackWalking {
+        self.max_argc = max_argc;
+        self
+    }
+}
+
+impl Default for StackWalking {
+    fn default() -> StackWalking {
+        StackWalking::new()
+    }
+}
+
+unsafe impl ArgvSource for StackWalking {
+    fn name(&self) -> &'static str {
+        "stack_walking"
+    }
+
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError> {
+        argv_addr::stack_walking(self.max_argc)
+    }
+}
+
+/// Finds argv from the argument area published in `/proc/self/stat`, Linux only.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Procfs;
+
+unsafe impl ArgvSource for Procfs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError> {
+        argv_addr::from_procfs()
+    }
+}
+
+/// Finds envp and argv from the auxiliary vector on the initial stack,
+/// anchored by `AT_EXECFN` and `AT_RANDOM`, Linux only.
+///
+/// Unlike [`Computed`] and [`StackWalking`], it does not depend on environ.
+#[derive(Clone, Copy, Debug)]
+pub struct Auxv {
+    max_argc: usize,
+}
+
+impl Auxv {
+    pub fn new() -> Auxv {
+        Auxv {
+            max_argc: stack_walk::DEFAULT_MAX_ARGC,
+        }
+    }
+
+    /// Give up when argc would be larger than this.
+    pub fn max_argc(mut self, max_argc: usize) -> Auxv {
+        self.max_argc = max_argc;
+        self
+    }
+}
+
+impl Default for Auxv {
+    fn default() -> Auxv {
+        Auxv::new()
+    }
+}
+
+unsafe impl ArgvSource for Auxv {
+    fn name(&self) -> &'static str {
+        "auxv"
+    }
+
+    fn find(&self) -> Result<(usize, *const *const c_char), EnvError> {
+        argv_addr::from_auxv(self.max_argc)
+    }
+}
+
+/// The sources used when none are given, as selected by the cargo features.
+pub fn default_sources() -> Vec<Arc<dyn ArgvSource>> {
+    let mut sources: Vec<Arc<dyn ArgvSource>> = vec![Arc::new(InitArray)];
+    if cfg!(feature = "force_walking") {
+        sources.push(Arc::new(StackWalking::new()));
+        return sources;
+    }
+    if cfg!(feature = "compute_argv") {
+        sources.push(Arc::new(Computed));
+    }
+    // Validated against the kernel, so it goes before the guesswork.
+    if cfg!(any(target_os = "linux", target_os = "android")) {
+        sources.push(Arc::new(Procfs));
+        sources.push(Arc::new(Auxv::new()));
+    }
+    if cfg!(feature = "stack_walking") {
+        sources.push(Arc::new(StackWalking::new()));
+    }
+    sources
+}
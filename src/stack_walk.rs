@@ -0,0 +1,465 @@
+use super::EnvError;
+use std::{ffi::c_char, mem::size_of};
+
+use log::{debug, trace};
+
+/// Default cap on the argc a walk will look for.
+pub(super) const DEFAULT_MAX_ARGC: usize = 1 << 16;
+
+// Part of the Linux ABI, they never change.
+const AT_NULL: u64 = 0;
+const AT_RANDOM: u64 = 25;
+const AT_EXECFN: u64 = 31;
+// Above the highest AT_* type known today, envp pointers are never that small.
+const AT_TYPE_MAX: u64 = 255;
+
+/// The memory a walk goes through.
+///
+/// argc and every pointer on the initial stack take a full word,
+/// read in the byte order of the target, so nothing here assumes
+/// the word size or endianness of the host.
+pub(super) trait Memory {
+    /// Size of a word (pointer, argc) in bytes, 4 or 8.
+    fn word_size(&self) -> usize;
+
+    fn big_endian(&self) -> bool;
+
+    /// `[start, end)` of the stack mapping, if known.
+    fn bounds(&self) -> Option<(usize, usize)>;
+
+    /// Copy `buf.len()` bytes from `addr`, which was checked with [`Memory::contains`].
+    fn read(&self, addr: usize, buf: &mut [u8]);
+
+    /// Unknown bounds contain nothing, nothing is read without them.
+    fn contains(&self, addr: usize, len: usize) -> bool {
+        self.bounds()
+            .is_some_and(|(start, end)| addr >= start && addr.saturating_add(len) <= end)
+    }
+
+    fn word(&self, addr: usize) -> Option<u64> {
+        let word_size = self.word_size();
+        if !self.contains(addr, word_size) {
+            return None;
+        }
+        let mut buf = [0u8; 8];
+        let bytes = &mut buf[..word_size];
+        self.read(addr, bytes);
+        let fold = |acc: u64, &b: &u8| (acc << 8) | b as u64;
+        Some(if self.big_endian() {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    }
+
+    /// Whether a NUL terminated string starts at `addr` and ends inside the mapping.
+    fn is_cstr(&self, addr: usize) -> bool {
+        let Some((_, end)) = self.bounds() else {
+            return false;
+        };
+        if !self.contains(addr, 1) {
+            return false;
+        }
+        let mut byte = [0u8];
+        (addr..end).any(|addr| {
+            self.read(addr, &mut byte);
+            byte[0] == 0
+        })
+    }
+}
+
+/// The memory of the current process.
+pub(super) struct Native {
+    bounds: Option<(usize, usize)>,
+}
+
+impl Native {
+    /// # Safety
+    /// The bounds, if any, must be readable memory.
+    pub(super) unsafe fn new(bounds: Option<(usize, usize)>) -> Native {
+        Native { bounds }
+    }
+
+    /// Bounded by the mapping `addr` is in, where it is known (Linux),
+    /// without bounds nothing can be read.
+    pub(super) fn around(addr: usize) -> Native {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let bounds = crate::procfs::mapping(addr);
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let bounds = {
+            let _ = addr;
+            None
+        };
+        unsafe { Native::new(bounds) }
+    }
+}
+
+impl Memory for Native {
+    fn word_size(&self) -> usize {
+        size_of::<usize>()
+    }
+
+    fn big_endian(&self) -> bool {
+        cfg!(target_endian = "big")
+    }
+
+    fn bounds(&self) -> Option<(usize, usize)> {
+        self.bounds
+    }
+
+    fn read(&self, addr: usize, buf: &mut [u8]) {
+        unsafe { (addr as *const u8).copy_to_nonoverlapping(buf.as_mut_ptr(), buf.len()) }
+    }
+}
+
+// from: https://github.com/leo60228/libargs/blob/master/src/lib.rs#L16-L30
+// The initial stack looks like this, growing down:
+//   argc, argv[0], ..., argv[argc - 1], NULL, envp[0], ..., NULL, auxv...
+// so we go backwards from envp until the slot holds the number of slots passed.
+pub(super) fn walk_in(
+    mem: &impl Memory,
+    environ: usize,
+    max_argc: usize,
+) -> Result<(usize, usize), &'static str> {
+    if mem.bounds().is_none() {
+        return Err("the stack mapping is unknown");
+    }
+    let word = mem.word_size();
+    let terminator = environ.wrapping_sub(word);
+    match mem.word(terminator) {
+        None => return Err("environ is at the bottom of the stack"),
+        Some(0) => {}
+        Some(_) => return Err("no NULL terminated argv right before environ"),
+    }
+
+    for argc in 0..=max_argc {
+        let slot = terminator.wrapping_sub((argc + 1) * word);
+        let value = mem.word(slot).ok_or("walked off the stack")?;
+        trace!("walking: slot={slot:#x}, value={value:#x}, expect argc={argc}");
+        if value == argc as u64 {
+            return Ok((argc, slot + word));
+        }
+        // Not argc, so it has to be argv[argc - 1 - ...], a string on the stack.
+        if value == 0 {
+            return Err("found NULL where an argv element was expected");
+        }
+        if !mem.is_cstr(value as usize) {
+            return Err("argv element is not a NUL terminated string on the stack");
+        }
+    }
+    Err("argc is larger than the configured maximum")
+}
+
+pub(super) fn walk(
+    environ: *const *const c_char,
+    max_argc: usize,
+) -> Result<(usize, *const *const c_char), EnvError> {
+    let mem = Native::around(environ as usize);
+    debug!(
+        "stack walking from environ={environ:?}, stack={:x?}, max argc={max_argc}",
+        mem.bounds()
+    );
+    walk_in(&mem, environ as usize, max_argc)
+        .map(|(argc, argv)| (argc, argv as *const *const c_char))
+        .map_err(|reason| EnvError::StackWalkFailed {
+            ptr: environ,
+            reason,
+        })
+}
+
+/// Find envp from the auxiliary vector, it sits right after the envp NULL
+/// terminator on the initial stack and does not move when environ does.
+///
+/// `AT_EXECFN` locates the vector inside the stack mapping, `AT_RANDOM`
+/// confirms it is the right one.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(super) fn envp_from_auxv(
+    mem: &impl Memory,
+    execfn: u64,
+    random: u64,
+) -> Result<usize, &'static str> {
+    let word = mem.word_size();
+    let (start, end) = mem.bounds().ok_or("the stack mapping is unknown")?;
+    let read = |addr: usize| mem.word(addr);
+    let in_stack = |value: u64| (start as u64..end as u64).contains(&value);
+
+    // The strings are at the very top, the vector is somewhere below them.
+    let top = (end - 2 * word) & !(word - 1);
+    let entry = (start..=top)
+        .rev()
+        .step_by(word)
+        .find(|&addr| read(addr) == Some(AT_EXECFN) && read(addr + word) == Some(execfn))
+        .ok_or("AT_EXECFN is not in the stack mapping")?;
+    trace!("auxv AT_EXECFN entry at {entry:#x}");
+
+    // Go back to auxv[0], every type before ours is small and non-zero.
+    let mut auxv = entry;
+    while read(auxv.wrapping_sub(2 * word)).is_some_and(|t| (1..=AT_TYPE_MAX).contains(&t)) {
+        auxv -= 2 * word;
+    }
+    let confirmed = (auxv..end - word)
+        .step_by(2 * word)
+        .take_while(|&addr| read(addr) != Some(AT_NULL))
+        .any(|addr| read(addr) == Some(AT_RANDOM) && read(addr + word) == Some(random));
+    if !confirmed {
+        return Err("AT_RANDOM does not match the auxiliary vector");
+    }
+    trace!("auxv[0] at {auxv:#x}");
+
+    // unsetenv() shifts envp in place and leaves extra NULLs before auxv.
+    let mut terminator = auxv - word;
+    while read(terminator.wrapping_sub(word)) == Some(0) {
+        terminator -= word;
+    }
+    // Below are either envp elements ending at the argv NULL terminator,
+    // or, with an empty environment, argv elements ending at argc.
+    let mut slot = terminator.wrapping_sub(word);
+    while read(slot).is_some_and(in_stack) {
+        slot -= word;
+    }
+    if read(slot) == Some(0) {
+        Ok(slot + word)
+    } else {
+        Ok(terminator + word)
+    }
+}
+
+/// Find argv from the auxiliary vector, see [`envp_from_auxv`].
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn from_auxv(max_argc: usize) -> Result<(usize, *const *const c_char), EnvError> {
+    let execfn = unsafe { libc::getauxval(libc::AT_EXECFN) };
+    let random = unsafe { libc::getauxval(libc::AT_RANDOM) };
+    debug!("auxv anchors: AT_EXECFN={execfn:#x}, AT_RANDOM={random:#x}");
+    let failed = |reason| EnvError::StackWalkFailed {
+        ptr: execfn as *const *const c_char,
+        reason,
+    };
+    if execfn == 0 || random == 0 {
+        return Err(failed("AT_EXECFN or AT_RANDOM is missing"));
+    }
+    let mem = Native::around(execfn as usize);
+    let envp = envp_from_auxv(&mem, execfn as u64, random as u64).map_err(failed)?;
+    debug!("envp computed from auxv: {envp:#x}");
+    walk(envp as *const *const c_char, max_argc)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn from_auxv(_max_argc: usize) -> Result<(usize, *const *const c_char), EnvError> {
+    Err(EnvError::ProcfsUnavailable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A made up initial stack, as the kernel would lay it out on any target.
+    struct Image {
+        base: usize,
+        bytes: Vec<u8>,
+        word_size: usize,
+        big_endian: bool,
+        /// Whether the mapping is known, as it is only on Linux.
+        bounded: bool,
+    }
+
+    impl Memory for Image {
+        fn word_size(&self) -> usize {
+            self.word_size
+        }
+
+        fn big_endian(&self) -> bool {
+            self.big_endian
+        }
+
+        fn bounds(&self) -> Option<(usize, usize)> {
+            self.bounded
+                .then_some((self.base, self.base + self.bytes.len()))
+        }
+
+        fn read(&self, addr: usize, buf: &mut [u8]) {
+            let offset = addr - self.base;
+            buf.copy_from_slice(&self.bytes[offset..offset + buf.len()]);
+        }
+    }
+
+    struct Layout {
+        image: Image,
+        argv: usize,
+        envp: usize,
+        execfn: u64,
+        random: u64,
+    }
+
+    impl Image {
+        fn put_word(&mut self, offset: usize, value: u64) {
+            let bytes = if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            let bytes = if self.big_endian {
+                &bytes[8 - self.word_size..]
+            } else {
+                &bytes[..self.word_size]
+            };
+            self.bytes[offset..offset + self.word_size].copy_from_slice(bytes);
+        }
+    }
+
+    // argc, argv, NULL, envp, NULL, auxv, AT_NULL, then the strings on top.
+    fn layout(word_size: usize, big_endian: bool, args: &[&str], envs: &[&str]) -> Layout {
+        let base = if word_size == 4 {
+            0xbff0_0000
+        } else {
+            0x7ffd_0000_0000
+        };
+        let slots = 1 + args.len() + 1 + envs.len() + 1 + 3 * 2;
+        let strings_len = args.iter().chain(envs).map(|s| s.len() + 1).sum::<usize>();
+        let mut image = Image {
+            base,
+            // Some room below argc, and the 16 AT_RANDOM bytes on top.
+            bytes: vec![0xaa; 4 * word_size + slots * word_size + strings_len + 16],
+            word_size,
+            big_endian,
+            bounded: true,
+        };
+        let mut string = 4 * word_size + slots * word_size;
+        let mut slot = 4 * word_size;
+        let argv = base + slot + word_size;
+        image.put_word(slot, args.len() as u64);
+        for s in args.iter().chain([&""]).chain(envs).chain([&""]) {
+            slot += word_size;
+            if s.is_empty() {
+                image.put_word(slot, 0);
+                continue;
+            }
+            image.put_word(slot, (base + string) as u64);
+            image.bytes[string..string + s.len()].copy_from_slice(s.as_bytes());
+            image.bytes[string + s.len()] = 0;
+            string += s.len() + 1;
+        }
+        let envp = argv + (args.len() + 1) * word_size;
+        let execfn = (base + 4 * word_size + slots * word_size) as u64;
+        let random = (base + string) as u64;
+        for (key, value) in [(AT_RANDOM, random), (AT_EXECFN, execfn), (AT_NULL, 0)] {
+            slot += word_size;
+            image.put_word(slot, key);
+            slot += word_size;
+            image.put_word(slot, value);
+        }
+        Layout {
+            image,
+            argv,
+            envp,
+            execfn,
+            random,
+        }
+    }
+
+    const TARGETS: [(usize, bool); 4] = [(8, false), (8, true), (4, false), (4, true)];
+
+    #[test]
+    fn test_walk_every_layout() {
+        for (word_size, big_endian) in TARGETS {
+            let l = layout(
+                word_size,
+                big_endian,
+                &["/bin/svc", "-v", "x"],
+                &["A=1", "B=2"],
+            );
+            assert_eq!(
+                walk_in(&l.image, l.envp, DEFAULT_MAX_ARGC),
+                Ok((3, l.argv)),
+                "word_size={word_size}, big_endian={big_endian}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_walk_argc_needs_full_word() {
+        // Reading less than the full word gives 0 here on big-endian targets.
+        let args = vec!["a"; 256];
+        for (word_size, big_endian) in TARGETS {
+            let l = layout(word_size, big_endian, &args, &["A=1"]);
+            assert_eq!(
+                walk_in(&l.image, l.envp, DEFAULT_MAX_ARGC),
+                Ok((256, l.argv))
+            );
+        }
+    }
+
+    #[test]
+    fn test_walk_max_argc() {
+        let l = layout(8, false, &["/bin/svc", "-v", "x"], &["A=1"]);
+        assert_eq!(
+            walk_in(&l.image, l.envp, 2),
+            Err("argc is larger than the configured maximum")
+        );
+    }
+
+    #[test]
+    fn test_walk_stays_in_bounds() {
+        let mut l = layout(4, true, &["/bin/svc", "-v"], &["A=1"]);
+        // Cut the image right at argv[0], there is no argc to find.
+        let cut = l.argv - l.image.base;
+        l.image.bytes.drain(..cut);
+        l.image.base += cut;
+        assert_eq!(
+            walk_in(&l.image, l.envp, DEFAULT_MAX_ARGC),
+            Err("walked off the stack")
+        );
+    }
+
+    #[test]
+    fn test_walk_needs_bounds() {
+        let mut l = layout(8, false, &["/bin/svc", "-v"], &["A=1"]);
+        l.image.bounded = false;
+        assert_eq!(
+            walk_in(&l.image, l.envp, DEFAULT_MAX_ARGC),
+            Err("the stack mapping is unknown")
+        );
+    }
+
+    #[test]
+    fn test_walk_rejects_bad_argv_element() {
+        let mut l = layout(8, false, &["/bin/svc", "-v"], &["A=1"]);
+        l.image.put_word(l.argv - l.image.base + 8, 0x1234);
+        assert_eq!(
+            walk_in(&l.image, l.envp, DEFAULT_MAX_ARGC),
+            Err("argv element is not a NUL terminated string on the stack")
+        );
+    }
+
+    #[test]
+    fn test_envp_from_auxv_every_layout() {
+        for (word_size, big_endian) in TARGETS {
+            for envs in [&["A=1", "B=2"][..], &[]] {
+                let l = layout(word_size, big_endian, &["/bin/svc", "-v"], envs);
+                assert_eq!(
+                    envp_from_auxv(&l.image, l.execfn, l.random),
+                    Ok(l.envp),
+                    "word_size={word_size}, big_endian={big_endian}, envs={envs:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_envp_from_auxv_after_unsetenv() {
+        let mut l = layout(8, true, &["/bin/svc"], &["A=1", "B=2"]);
+        // unsetenv("A") shifts B down and leaves two NULLs behind.
+        let b = l.image.word(l.envp + 8).unwrap();
+        l.image.put_word(l.envp - l.image.base, b);
+        l.image.put_word(l.envp - l.image.base + 8, 0);
+        assert_eq!(envp_from_auxv(&l.image, l.execfn, l.random), Ok(l.envp));
+    }
+
+    #[test]
+    fn test_envp_from_auxv_wrong_random() {
+        let l = layout(8, false, &["/bin/svc"], &["A=1"]);
+        assert_eq!(
+            envp_from_auxv(&l.image, l.execfn, l.random + 1),
+            Err("AT_RANDOM does not match the auxiliary vector")
+        );
+    }
+}
@@ -0,0 +1,22 @@
+use log::trace;
+
+// Relocating environ (aka setenv) races with every getenv in other threads,
+// so we need to know whether we are alone before touching it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn count() -> Option<usize> {
+    let count = std::fs::read_dir("/proc/self/task")
+        .map_err(|e| trace!("failed to read /proc/self/task: {e}"))
+        .ok()?
+        .filter(|entry| entry.is_ok())
+        .count();
+    trace!("thread count from /proc/self/task: {count}");
+    Some(count)
+}
+
+// There is no cheap and portable way to count threads elsewhere,
+// the caller decides what an unknown count means.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(super) fn count() -> Option<usize> {
+    trace!("thread count is unknown on this target");
+    None
+}
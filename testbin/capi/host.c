@@ -0,0 +1,31 @@
+/* A C program sharing the process title with Rust code through the C ABI. */
+#include <stdio.h>
+#include <string.h>
+
+#include "killmyargv.h"
+
+int rust_set(const char *title);
+
+int main(void) {
+    if (killmyargv_init() != 0) {
+        return 1;
+    }
+    printf("%zu\n", killmyargv_max_len());
+    fflush(stdout);
+
+    char line[4096];
+    while (fgets(line, sizeof line, stdin)) {
+        line[strcspn(line, "\n")] = '\0';
+        int ret;
+        if (strcmp(line, "!revert") == 0) {
+            ret = killmyargv_revert();
+        } else if (strncmp(line, "rust:", 5) == 0) {
+            ret = rust_set(line + 5);
+        } else {
+            ret = killmyargv_set(line);
+        }
+        printf(ret == 0 ? "set done\n" : "set failed\n");
+        fflush(stdout);
+    }
+    return 0;
+}
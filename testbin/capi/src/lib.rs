@@ -0,0 +1,20 @@
+use std::ffi::{c_char, c_int, CStr};
+
+// Exports the C ABI of the feature.
+pub use killmyargv::capi;
+
+/// The Rust half of the program, going through the same handle as the C half.
+///
+/// # Safety
+/// `title` has to be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn rust_set(title: *const c_char) -> c_int {
+    let title = unsafe { CStr::from_ptr(title) };
+    match killmyargv::global() {
+        Ok(mem) => {
+            mem.set(title.to_bytes());
+            0
+        }
+        Err(_) => -1,
+    }
+}
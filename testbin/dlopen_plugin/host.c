@@ -0,0 +1,41 @@
+/* A C program loading a Rust plugin at runtime, long after startup. */
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+int main(int argc, char **argv) {
+    if (argc < 2) {
+        fprintf(stderr, "usage: %s PLUGIN [ARGS...]\n", argv[0]);
+        return 2;
+    }
+    /* Sandboxing hosts hand plugins a clean environment, environ then no
+       longer points at the initial stack when the plugin is loaded. */
+    clearenv();
+    setenv("KILLMYARGV_HOST", "1", 1);
+
+    void *plugin = dlopen(argv[1], RTLD_NOW);
+    if (!plugin) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    int (*plugin_init)(void) = (int (*)(void))dlsym(plugin, "plugin_init");
+    void (*plugin_set)(const char *) = (void (*)(const char *))dlsym(plugin, "plugin_set");
+    if (!plugin_init || !plugin_set) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    if (plugin_init() != 0) {
+        return 1;
+    }
+    fflush(stdout);
+
+    char line[4096];
+    while (fgets(line, sizeof line, stdin)) {
+        line[strcspn(line, "\n")] = '\0';
+        plugin_set(line);
+        printf("set done\n");
+        fflush(stdout);
+    }
+    return 0;
+}
@@ -0,0 +1,35 @@
+use std::{
+    ffi::{c_char, c_int, CStr},
+    sync::{Mutex, OnceLock},
+};
+
+use killmyargv::KillMyArgv;
+
+static HANDLE: OnceLock<Mutex<KillMyArgv>> = OnceLock::new();
+
+/// Prints the source argv was found with and max_len, returns non-zero on failure.
+#[no_mangle]
+pub extern "C" fn plugin_init() -> c_int {
+    match KillMyArgv::new() {
+        Ok(handle) => {
+            println!("{}", handle.source());
+            println!("{}", handle.max_len());
+            HANDLE.get_or_init(|| Mutex::new(handle));
+            0
+        }
+        Err(e) => {
+            eprintln!("{e:?}");
+            1
+        }
+    }
+}
+
+/// # Safety
+/// `title` has to be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn plugin_set(title: *const c_char) {
+    if let Some(handle) = HANDLE.get() {
+        let title = unsafe { CStr::from_ptr(title) };
+        handle.lock().unwrap().set(title.to_bytes());
+    }
+}
@@ -8,15 +8,289 @@ use base64::{
     engine::{general_purpose::GeneralPurpose, GeneralPurposeConfig},
     Engine,
 };
-use killmyargv::KillMyArgv;
+use killmyargv::{
+    ArgOverflow, Auxv, Builder, Computed, InitArray, KillMyArgv, ModifiedPolicy, Procfs,
+    StackWalking, TitlePrefix,
+};
+
+// Stands in for a C `main` handing its argc/argv/envp over to us.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+mod raw_parts {
+    use std::ffi::{c_char, c_int};
+    use std::sync::atomic::{AtomicI32, AtomicPtr, Ordering};
+
+    static ARGC: AtomicI32 = AtomicI32::new(0);
+    static ARGV: AtomicPtr<*const c_char> = AtomicPtr::new(std::ptr::null_mut());
+    static ENVP: AtomicPtr<*const c_char> = AtomicPtr::new(std::ptr::null_mut());
+
+    #[used]
+    #[link_section = ".init_array"]
+    static INIT_ARRAY: extern "C" fn(c_int, *const *const c_char, *const *const c_char) = {
+        extern "C" fn init(argc: c_int, argv: *const *const c_char, envp: *const *const c_char) {
+            ARGC.store(argc, Ordering::Relaxed);
+            ARGV.store(argv as *mut _, Ordering::Relaxed);
+            ENVP.store(envp as *mut _, Ordering::Relaxed);
+        }
+        init
+    };
+
+    pub fn get() -> (c_int, *const *const c_char, *const *const c_char) {
+        (
+            ARGC.load(Ordering::Relaxed),
+            ARGV.load(Ordering::Relaxed),
+            ENVP.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// Options are taken from the arguments, so they also show up in the cmdline.
+fn builder_from_args() -> Result<Builder, Box<dyn Error>> {
+    let mut builder = KillMyArgv::builder();
+    for arg in std::env::args().skip(1) {
+        if let Some(source) = arg.strip_prefix("--source=") {
+            builder = match source {
+                "init_array" => builder.source(InitArray),
+                "computed" => builder.source(Computed),
+                "procfs" => builder.source(Procfs),
+                "auxv" => builder.source(Auxv::new()),
+                "stack_walking" => {
+                    let mut walking = StackWalking::new();
+                    if let Some(max_argc) = std::env::args()
+                        .find_map(|arg| arg.strip_prefix("--max-argc=").map(str::parse))
+                    {
+                        walking = walking.max_argc(max_argc?);
+                    }
+                    builder.source(walking)
+                }
+                _ => return Err(format!("unknown source: {source}").into()),
+            };
+        } else if arg == "--procfs-region" {
+            builder = builder.procfs_region(true);
+        } else if arg == "--revert-on-drop" {
+            builder = builder.revert_on_drop(true);
+        } else if arg == "--minimal-clobber" {
+            builder = builder.minimal_clobber(true);
+        } else if arg == "--cooperative" {
+            builder = builder.cooperative(true);
+        } else if let Some(policy) = arg.strip_prefix("--modified-policy=") {
+            builder = builder.modified_policy(match policy {
+                "warn" => ModifiedPolicy::Warn,
+                "error" => ModifiedPolicy::Error,
+                "overwrite" => ModifiedPolicy::Overwrite,
+                _ => return Err(format!("unknown policy: {policy}").into()),
+            });
+        } else if let Some(policy) = arg.strip_prefix("--arg-overflow=") {
+            builder = builder.arg_overflow(match policy {
+                "error" => ArgOverflow::Error,
+                "truncate" => ArgOverflow::Truncate,
+                _ => return Err(format!("unknown policy: {policy}").into()),
+            });
+        } else if let Some(prefix) = arg.strip_prefix("--title-prefix=") {
+            builder = builder.title_prefix(title_prefix(prefix)?);
+        }
+    }
+    // For a cmdline of nothing but argv[0].
+    if let Ok(prefix) = std::env::var("KILLMYARGV_TITLE_PREFIX") {
+        builder = builder.title_prefix(title_prefix(&prefix)?);
+    }
+    Ok(builder)
+}
+
+fn title_prefix(prefix: &str) -> Result<TitlePrefix, Box<dyn Error>> {
+    Ok(match prefix {
+        "argv0" => TitlePrefix::Argv0,
+        "basename" => TitlePrefix::Basename,
+        "progname" => TitlePrefix::Progname,
+        _ => return Err(format!("unknown prefix: {prefix}").into()),
+    })
+}
+
+// What the setproctitle_init of libbsd does before owning the argv and environ area:
+// copy the arguments and variables elsewhere and point argv and environ at the copies.
+fn take_over() -> Result<(), Box<dyn Error>> {
+    use std::ffi::{c_char, CStr};
+    extern "C" {
+        static mut environ: *const *const c_char;
+    }
+    let (argc, argv) = unsafe { killmyargv::argc_argv()? };
+    let argv = argv as *mut *const c_char;
+    for i in 1..argc {
+        unsafe {
+            let copy = CStr::from_ptr(argv.add(i).read()).to_owned().into_raw();
+            argv.add(i).write(copy);
+        }
+    }
+    let mut envp = Vec::new();
+    let mut env = unsafe { environ };
+    while !unsafe { env.read() }.is_null() {
+        unsafe {
+            envp.push(CStr::from_ptr(env.read()).to_owned().into_raw() as *const c_char);
+            env = env.add(1);
+        }
+    }
+    envp.push(std::ptr::null());
+    unsafe { environ = envp.leak().as_ptr() };
+    Ok(())
+}
+
+// A pre-fork server: forks workers while another thread keeps setting the title of
+// the parent, every worker then sets its own title and prints its index and pid.
+// The first worker is forked while holding the global handle.
+fn fork_workers(workers: usize) -> Result<(), Box<dyn Error>> {
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+    let started = Arc::new(Barrier::new(2));
+    let parent = std::thread::spawn({
+        let started = started.clone();
+        move || {
+            killmyargv::global()?.try_set(b"parent")?;
+            started.wait();
+            while !STOP.load(Ordering::Relaxed) {
+                killmyargv::global()?.try_set(b"parent")?;
+                std::thread::sleep(std::time::Duration::from_micros(100));
+            }
+            Ok::<_, killmyargv::EnvError>(())
+        }
+    });
+    started.wait();
+    let mut children = Vec::new();
+    for i in 0..workers {
+        std::io::stdout().flush()?;
+        let held = (i == 0).then(killmyargv::global).transpose()?;
+        match unsafe { libc::fork() } {
+            -1 => return Err(std::io::Error::last_os_error().into()),
+            0 => {
+                drop(held);
+                for _ in 0..100 {
+                    killmyargv::global()?.try_set(format!("worker {i}").as_bytes())?;
+                }
+                println!("{i} {}", std::process::id());
+                // Until the test is done looking.
+                for _ in stdin().lock().lines() {}
+                unsafe { libc::_exit(0) };
+            }
+            pid => children.push(pid),
+        }
+    }
+    println!("parent {}", std::process::id());
+    for _ in stdin().lock().lines() {}
+    STOP.store(true, Ordering::Relaxed);
+    parent.join().map_err(|_| "parent thread panicked")??;
+    for pid in children {
+        unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+    }
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
+    if let Some(workers) =
+        std::env::args().find_map(|arg| arg.strip_prefix("--fork-workers=").map(str::parse))
+    {
+        return fork_workers(workers?);
+    }
+
     let stdin = stdin().lock();
     let alphabet = alphabet::STANDARD;
     let config = GeneralPurposeConfig::default();
     let engine = GeneralPurpose::new(&alphabet, config);
 
-    let kill_my_argv = KillMyArgv::new()?;
+    if std::env::args().any(|arg| arg == "--setenv-first") {
+        // Reallocates environ before we get a chance to look at it.
+        std::env::set_var("KILLMYARGV_SETENV_FIRST", "1");
+    }
+
+    if std::env::args().any(|arg| arg == "--spawn-thread") {
+        std::thread::spawn(|| loop {
+            std::thread::park();
+        });
+    }
+
+    if let Some(name) =
+        std::env::args().find_map(|arg| arg.strip_prefix("--unsetenv=").map(str::to_owned))
+    {
+        // Shrinks the original environ array in place.
+        std::env::remove_var(name);
+    }
+
+    let stray_arg =
+        std::env::args().find_map(|arg| arg.strip_prefix("--stray-arg=").map(str::parse));
+    if let Some(index) = stray_arg.clone() {
+        // Like a commandline parser moving an argument somewhere else.
+        let (_, argv) = unsafe { killmyargv::argc_argv()? };
+        let moved = std::ffi::CString::new("moved")?.into_raw();
+        unsafe {
+            (argv as *mut *const std::ffi::c_char)
+                .add(index?)
+                .write(moved)
+        };
+    }
+
+    if std::env::args().any(|arg| arg == "--other-setproctitle") {
+        take_over()?;
+    }
+
+    if std::env::args().any(|arg| arg == "--diagnose-first") {
+        // Without a handle yet, nothing may be moved by the report.
+        let (_, argv) = unsafe { killmyargv::argc_argv()? };
+        let before = unsafe { (killmyargv::environ(), *argv) };
+        let report = killmyargv::diagnose();
+        let after = unsafe { (killmyargv::environ(), *argv) };
+        println!("{:?} {}", report.max_len, before == after);
+    }
+
+    let builder = builder_from_args()?;
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    let kill_my_argv = if std::env::args().any(|arg| arg == "--raw-parts") {
+        let (argc, argv, envp) = raw_parts::get();
+        unsafe { builder.build_from_raw_parts(argc, argv, envp)? }
+    } else {
+        builder.build()?
+    };
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    let kill_my_argv = builder.build()?;
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    if std::env::args().any(|arg| arg == "--raw-parts-mismatch") {
+        // Not the argv found by build(), the region must stay the same.
+        let (argc, argv, envp) = raw_parts::get();
+        let other = unsafe { KillMyArgv::from_raw_parts(argc - 1, argv.add(1), envp) };
+        let (found_argc, _) = unsafe { killmyargv::argc_argv()? };
+        println!("{:?} {found_argc}", other.map(|_| ()));
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    if std::env::args().any(|arg| arg == "--global-raw-parts") {
+        // Initializes the global handle, then asks for it with an argv that is not ours.
+        let (argc, argv, envp) = raw_parts::get();
+        let source =
+            unsafe { killmyargv::global_from_raw_parts(argc, argv, envp) }.map(|mem| mem.source());
+        let other = unsafe { killmyargv::global_from_raw_parts(argc - 1, argv.add(1), envp) };
+        println!("{source:?} {:?}", other.map(|_| ()));
+    }
+
+    if let Some(capacity) =
+        std::env::args().find_map(|arg| arg.strip_prefix("--ensure-capacity=").map(str::parse))
+    {
+        // Before anything is printed, the program may start over.
+        let result = kill_my_argv.ensure_capacity(capacity?);
+        let padding_visible = std::env::var_os("KILLMYARGV_PADDING").is_some();
+        match result {
+            Ok(()) => println!(
+                "{} {padding_visible} {}",
+                kill_my_argv.max_len(),
+                std::env::args().collect::<Vec<_>>().join(" ")
+            ),
+            Err(e) => println!("{padding_visible} {e}"),
+        }
+    }
+
+    let source = std::env::args().find(|arg| arg.starts_with("--source="));
+    if source.is_some() {
+        println!("{}", kill_my_argv.source());
+    }
 
     if let Some(output_argv_max_len) = std::env::args().nth(1) {
         if "true" == &output_argv_max_len {
@@ -24,12 +298,114 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    if stray_arg.is_some() {
+        let strays = kill_my_argv
+            .stray_pointers()
+            .iter()
+            .map(|stray| format!("{}:{}", stray.area, stray.index))
+            .collect::<Vec<_>>();
+        println!("{}", strays.join(","));
+    }
+
+    if std::env::args().any(|arg| arg == "--layout") {
+        let layout = kill_my_argv.layout();
+        let entries = |entries: &[killmyargv::Entry]| {
+            entries
+                .iter()
+                .map(|entry| match entry.offset {
+                    Some(offset) => format!("{}:{offset}:{}", entry.index, entry.len),
+                    None => format!("{}:-:{}", entry.index, entry.len),
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        println!("{} {:?}", layout.region.len, layout.gap);
+        println!("{}", entries(&layout.argv_entries));
+        println!("{}", entries(&layout.environ_entries));
+    }
+
+    if std::env::args().any(|arg| arg == "--then-global") {
+        // The environ relocated above must not look like a takeover.
+        let second = KillMyArgv::new().map(|_| ());
+        let global = killmyargv::global().map(|_| ());
+        println!("{second:?} {global:?}");
+    }
+
+    if std::env::args().any(|arg| arg == "--then-clearenv") {
+        // The program setting every variable again, environ moves once more.
+        let vars = std::env::vars_os().collect::<Vec<_>>();
+        for (key, _) in &vars {
+            std::env::remove_var(key);
+        }
+        for (key, value) in vars {
+            std::env::set_var(key, value);
+        }
+        println!("{:?}", KillMyArgv::new().map(|_| ()));
+    }
+
+    if std::env::args().any(|arg| arg == "--then-take-over") {
+        // Another implementation coming after us.
+        take_over()?;
+        println!("{:?}", KillMyArgv::new().map(|_| ()));
+    }
+
+    if std::env::args().any(|arg| arg == "--diagnose-locked") {
+        // Held by this thread, waiting for it would never return.
+        let _guard = killmyargv::global()?;
+        println!("{:?}", killmyargv::diagnose().error);
+    }
+
+    if std::env::args().any(|arg| arg == "--diagnose") {
+        let report = kill_my_argv.diagnose();
+        eprint!("{report}");
+        println!("{}", report.to_json());
+    }
+
     for next_cmd_line in stdin.lines() {
         let cmd_line = next_cmd_line?;
-        let cmd_line = engine.decode(&cmd_line)?;
-        kill_my_argv.set(&cmd_line);
-
-        println!("set done");
+        // Not base64, so it can not clash with a title.
+        if cmd_line == "!revert_full" {
+            if let Err(e) = kill_my_argv.try_revert_full() {
+                println!("set failed: {e}");
+                continue;
+            }
+            let (argc, argv) = unsafe { killmyargv::argc_argv()? };
+            let restored =
+                kill_my_argv.layout().argv_entries.iter().all(|entry| {
+                    entry.index < argc && unsafe { *argv.add(entry.index) } == entry.ptr
+                });
+            println!("{restored}");
+            continue;
+        }
+        if cmd_line == "!scribble" {
+            // Like a C library writing into argv[0] behind our back.
+            let start = kill_my_argv.layout().region.start as *mut u8;
+            unsafe { start.write(start.read() ^ 0x20) };
+            println!("scribbled");
+            continue;
+        }
+        if cmd_line == "!modified" {
+            println!("{}", kill_my_argv.has_been_modified());
+            continue;
+        }
+        if cmd_line == "!args" {
+            println!("{}", std::env::args().collect::<Vec<_>>().join(" "));
+            continue;
+        }
+        let mut words = cmd_line.split(' ');
+        let result = match (words.next(), words.next(), words.next()) {
+            (Some("!revert"), None, None) => kill_my_argv.try_revert(),
+            (Some("!set_arg"), Some(i), Some(value)) => {
+                kill_my_argv.set_arg(i.parse()?, &engine.decode(value)?)
+            }
+            (Some("!remove_arg"), Some(i), None) => kill_my_argv.remove_arg(i.parse()?),
+            (Some("!push_arg"), Some(value), None) => kill_my_argv.push_arg(&engine.decode(value)?),
+            _ => kill_my_argv.try_set(&engine.decode(&cmd_line)?),
+        };
+        match result {
+            Ok(()) => println!("set done"),
+            Err(e) => println!("set failed: {e}"),
+        }
     }
 
     Ok(())
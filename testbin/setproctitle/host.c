@@ -0,0 +1,25 @@
+/* A legacy daemon written against BSD setproctitle(3). */
+#include <stdio.h>
+#include <string.h>
+
+void setproctitle_init(int argc, char *argv[], char *envp[]);
+void setproctitle(const char *fmt, ...);
+
+int main(int argc, char *argv[], char *envp[]) {
+    setproctitle_init(argc, argv, envp);
+
+    char line[1024];
+    while (fgets(line, sizeof line, stdin)) {
+        line[strcspn(line, "\n")] = '\0';
+        if (strcmp(line, "!restore") == 0) {
+            setproctitle(NULL);
+        } else if (line[0] == '-') {
+            setproctitle("-%s %d %.1f", line + 1, 42, 1.5);
+        } else {
+            setproctitle("%s %d %.1f", line, 42, 1.5);
+        }
+        printf("set done\n");
+        fflush(stdout);
+    }
+    return 0;
+}
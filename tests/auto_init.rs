@@ -0,0 +1,45 @@
+use utils::set_cmdline_with_child;
+
+#[test]
+fn test_auto_init_before_threads() -> Result<()> {
+    let auto_init_path = build_test_binary("auto_init", "testbin")?;
+    let envs = [("A", "aaaa"), ("BB", "b")];
+    let mut child = Command::new(&auto_init_path)
+        .env_clear()
+        .envs(envs)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let child_pid = child.id();
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    let mut reader = linereader::LineReader::new(child_stdout);
+    let max_len = reader.next_line().unwrap()?;
+    let max_len: usize = String::from_utf8_lossy(max_len).trim().parse()?;
+
+    // The thread spawned in main did not stop environ from being used.
+    let env_len = envs
+        .iter()
+        .map(|(k, v)| k.len() + 1 + v.len() + 1)
+        .sum::<usize>()
+        - 1;
+    assert_eq!(max_len, auto_init_path.len() + 1 + env_len);
+
+    let input = "o".repeat(max_len);
+    set_cmdline_with_child(
+        [input.clone()],
+        [vec![input]],
+        child_stdin,
+        reader.into_inner(),
+        child_pid,
+    )?;
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::{Command, Stdio};
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+use test_binary::build_test_binary;
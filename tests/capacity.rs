@@ -0,0 +1,70 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, Session};
+
+fn command(args: &[&str]) -> Result<Command> {
+    let mut command = Command::new(get_set_cmdline_path()?);
+    command.args(args).env_clear();
+    Ok(command)
+}
+
+#[test]
+fn test_ensure_capacity() -> Result<()> {
+    let mut session = Session::spawn(&mut command(&["--ensure-capacity=300", "x"])?)?;
+
+    // Re-executed in place with the padding, which the program does not see,
+    // nor its arguments.
+    let line = session.next_line()?;
+    let mut fields = line.splitn(3, ' ');
+    let max_len: usize = fields.next().ok_or("no max_len")?.parse()?;
+    assert!(max_len >= 300, "{line}");
+    assert_eq!(fields.next(), Some("false"), "{line}");
+    let args = fields.next().ok_or("no args")?;
+    assert!(args.ends_with(" --ensure-capacity=300 x"), "{line}");
+
+    let title = "t".repeat(299);
+    session.send_title(&title)?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_eq!(session.title()?, title.as_bytes());
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+#[test]
+fn test_ensure_capacity_once() -> Result<()> {
+    // As if restarted already, with too little padding.
+    let output = command(&["--ensure-capacity=300"])?
+        .env("KILLMYARGV_PADDING", "x")
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    // The padding is gone from the environment all the same.
+    assert!(stdout.starts_with("false Only "), "{stdout}");
+    assert!(stdout.ends_with(" bytes are available for the title, 300 are needed.\n"));
+    Ok(())
+}
+
+#[test]
+fn test_ensure_capacity_argv_only() -> Result<()> {
+    // Another thread keeps environ out of the region, padding it does not help.
+    let output = command(&["--spawn-thread", "--ensure-capacity=300"])?.output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    // Failed without restarting, the padding would be left over otherwise.
+    assert!(stdout.starts_with("false Only "), "{stdout}");
+    assert!(stdout.ends_with(", 300 are needed.\n"), "{stdout}");
+    Ok(())
+}
+
+#[test]
+fn test_ensure_capacity_over_limit() -> Result<()> {
+    let output = command(&["--ensure-capacity=1000000"])?.output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.ends_with(", 1000000 are needed.\n"), "{stdout}");
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,72 @@
+#![cfg(all(target_os = "linux", target_env = "gnu"))]
+
+use utils::{build_c_host, build_cdylib, Session};
+
+#[test]
+fn test_capi() -> Result<()> {
+    let lib = build_cdylib("testbin/capi", "killmyargv_capi")?;
+    let lib_dir = lib.parent().unwrap();
+    let include = Path::new(env!("CARGO_MANIFEST_DIR")).join("include");
+    let mut rpath = OsString::from("-Wl,-rpath,");
+    rpath.push(lib_dir);
+    let mut include_arg = OsString::from("-I");
+    include_arg.push(&include);
+    let mut lib_arg = OsString::from("-L");
+    lib_arg.push(lib_dir);
+    let args = [
+        include_arg.as_os_str(),
+        lib_arg.as_os_str(),
+        "-lkillmyargv_capi".as_ref(),
+        rpath.as_os_str(),
+    ];
+    let Some(host) = build_c_host("capi", &args)? else {
+        eprintln!("no C compiler, skipping");
+        return Ok(());
+    };
+    let mut session = Session::spawn(Command::new(&host).env_clear().env("AA", "1"))?;
+
+    let max_len: usize = session.next_line()?.parse()?;
+    assert_eq!(max_len, host.as_os_str().len() + 1 + "AA=1".len());
+    let original = session.title()?;
+
+    for (input, title) in [
+        ("from c", "from c"),
+        ("rust:from rust", "from rust"),
+        ("from c again", "from c again"),
+    ] {
+        session.send(input)?;
+        assert_eq!(session.next_line()?, "set done");
+        assert_eq!(session.title()?, title.as_bytes());
+    }
+    session.send("!revert")?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_eq!(session.title()?, original);
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+#[test]
+fn test_capi_header() -> Result<()> {
+    // What `cbindgen --verify` does, without needing the cbindgen binary.
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let config = cbindgen::Config::from_file(Path::new(dir).join("cbindgen.toml"))?;
+    let mut generated = Vec::new();
+    cbindgen::generate_with_config(dir, config)?.write(&mut generated);
+    let header = fs::read(Path::new(dir).join("include/killmyargv.h"))?;
+    assert!(
+        header == generated,
+        "include/killmyargv.h is out of date, regenerate it as told in cbindgen.toml:\n{}",
+        String::from_utf8_lossy(&generated)
+    );
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
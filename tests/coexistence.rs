@@ -0,0 +1,89 @@
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_taken_over() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .arg("--other-setproctitle")
+        .env("KILLMYARGV_TAKEN_OVER", "1")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("RegionTakenOver"), "{stderr}");
+    Ok(())
+}
+
+#[test]
+fn test_own_relocation_not_taken_over() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .arg("--then-global")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    // A second handle after the first moved environ, then the global one.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Ok(()) Ok(())\n");
+    Ok(())
+}
+
+#[test]
+fn test_setenv_again_not_taken_over() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .arg("--then-clearenv")
+        .env("AA", "1")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Ok(())\n");
+    Ok(())
+}
+
+#[test]
+fn test_taken_over_after_us() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .arg("--then-take-over")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    // Our own copies of argv are copied again.
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Err(RegionTakenOver)\n"
+    );
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_cooperative() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let args = ["true", "--other-setproctitle", "--cooperative"];
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .args(args)
+            .env_clear()
+            .env("AA", "1"),
+    )?;
+
+    // The whole area is still used, though argv[1] and later point elsewhere.
+    let argv_len = set_cmdline_path.len() + args.iter().map(|arg| 1 + arg.len()).sum::<usize>();
+    let max_len: usize = session.next_line()?.parse()?;
+    assert_eq!(max_len, argv_len + 1 + "AA=1".len());
+
+    let title = "c".repeat(max_len);
+    session.send_title(&title)?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_eq!(session.cmdline()?, [title.as_bytes(), b"\0"].concat());
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::{Command, Stdio};
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,23 @@
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_stray_argv_element() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session =
+        Session::spawn(Command::new(&set_cmdline_path).args(["true", "--stray-arg=2", "x"]))?;
+    let max_len: usize = session.next_line()?.parse()?;
+    let strays = session.next_line()?;
+    session.wait()?;
+
+    // Only argv[0] and argv[1] are left back to back, environ no longer follows.
+    assert_eq!(max_len, set_cmdline_path.len() + 1 + "true".len());
+    // argv[3] is still where the kernel put it, but past the moved argv[2].
+    assert_eq!(strays, "argv:2,argv:3");
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,104 @@
+use utils::get_set_cmdline_path;
+
+#[test]
+fn test_diagnose() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--diagnose", "--source=init_array"])
+        .env_clear()
+        .env("AA", "1")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("init_array"));
+    let max_len: usize = lines.next().unwrap().parse()?;
+    let json = lines.next().unwrap();
+
+    let argv_len = set_cmdline_path.len() + 1 + "true".len() + 1;
+    let argv_len = argv_len + "--diagnose".len() + 1 + "--source=init_array".len() + 1;
+    for field in [
+        format!("\"target\":\"{}\"", env!("TARGET")),
+        "\"error\":null".to_owned(),
+        "\"source\":\"init_array\"".to_owned(),
+        "\"argc\":4,\"argv_found\":4,\"argv_available\":4".to_owned(),
+        "\"environ_found\":1,\"environ_available\":1".to_owned(),
+        format!("\"argv_len\":{argv_len},\"environ_len\":5,\"environ_adjacent\":true"),
+        format!("\"max_len\":{max_len},\"nonul_byte\":{argv_len}"),
+    ] {
+        assert!(json.contains(&field), "{field} not in {json}");
+    }
+    if cfg!(target_os = "linux") {
+        assert!(json.contains("\"argv_matches\":true,\"environ_matches\":true"));
+    }
+    let display = String::from_utf8(output.stderr)?;
+    assert!(
+        display.contains("source:           init_array"),
+        "{display}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diagnose_before_init() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--diagnose-first"])
+        .env_clear()
+        .env("AA", "1")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut lines = stdout.lines();
+    let report = lines.next().unwrap();
+    let max_len: usize = lines.next().unwrap().parse()?;
+    // The same as found by the handle built afterwards, with argv and environ untouched.
+    assert_eq!(report, format!("Some({max_len}) true"));
+    Ok(())
+}
+
+#[test]
+fn test_diagnose_argv_only() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--spawn-thread", "--diagnose"])
+        .env_clear()
+        .env("AA", "1")
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let json = stdout.lines().last().unwrap();
+    // environ is left alone with another thread running, none of it is available.
+    for field in [
+        "\"environ_found\":0,\"environ_available\":0",
+        "\"environ_len\":null,\"environ_adjacent\":false",
+    ] {
+        assert!(json.contains(field), "{field} not in {json}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_diagnose_locked() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--diagnose-locked"])
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(
+        stdout.lines().last(),
+        Some("Some(\"The global handle is locked.\")")
+    );
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::{Command, Stdio};
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
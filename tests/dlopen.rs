@@ -0,0 +1,39 @@
+#![cfg(all(target_os = "linux", target_env = "gnu"))]
+
+use utils::{build_c_host, build_cdylib, Session};
+
+#[test]
+fn test_dlopen_plugin() -> Result<()> {
+    let plugin = build_cdylib("testbin/dlopen_plugin", "dlopen_plugin")?;
+    let Some(host) = build_c_host("dlopen_plugin", &["-ldl".as_ref()])? else {
+        eprintln!("no C compiler, skipping");
+        return Ok(());
+    };
+    let mut session = Session::spawn(
+        Command::new(&host)
+            .arg(&plugin)
+            .arg("x")
+            .env_clear()
+            .env("AA", "1"),
+    )?;
+
+    assert_eq!(session.next_line()?, "init_array");
+    // The host cleared its environment, the initial one is still found.
+    let argv_len = host.as_os_str().len() + 1 + plugin.as_os_str().len() + 1 + "x".len();
+    let max_len: usize = session.next_line()?.parse()?;
+    assert_eq!(max_len, argv_len + 1 + "AA=1".len());
+
+    session.send("plugin title")?;
+    assert_eq!(session.next_line()?, "set done");
+    let cmdline = session.cmdline()?;
+    assert!(cmdline.starts_with(b"plugin title\0"), "{cmdline:?}");
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,135 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{encode, get_set_cmdline_path, Session};
+
+fn spawn(args: &[&str]) -> Result<Session> {
+    let mut session = Session::spawn(Command::new(get_set_cmdline_path()?).args(args).env_clear())?;
+    // Until then the cmdline may still be the one of the test.
+    command(&mut session, "!args", None, None)?;
+    Ok(session)
+}
+
+fn command(
+    session: &mut Session,
+    cmd: &str,
+    i: Option<usize>,
+    value: Option<&str>,
+) -> Result<String> {
+    let mut line = cmd.to_owned();
+    if let Some(i) = i {
+        line += &format!(" {i}");
+    }
+    if let Some(value) = value {
+        line += &format!(" {}", encode(value));
+    }
+    session.send(&line)?;
+    session.next_line()
+}
+
+// The argv area keeps its size, the rest is NUL bytes.
+fn padded(args: &[&[u8]], len: usize) -> Vec<u8> {
+    let mut cmdline = args.join(&0);
+    cmdline.resize(len, 0);
+    cmdline
+}
+
+#[test]
+fn test_edit_args() -> Result<()> {
+    let path = get_set_cmdline_path()?;
+    let path = path.as_bytes();
+    let mut child = spawn(&["--daemonize", "--config", "/tmp/x"])?;
+    let original = child.cmdline()?;
+    let len = original.len();
+
+    // Dropping the flag makes room for a longer path, exactly.
+    assert_eq!(
+        command(&mut child, "!remove_arg", Some(1), None)?,
+        "set done"
+    );
+    let resolved = format!(
+        "/etc/{}",
+        "r".repeat("--daemonize /tmp/x".len() - "/etc/".len())
+    );
+    assert_eq!(
+        command(&mut child, "!set_arg", Some(2), Some(&resolved))?,
+        "set done"
+    );
+    let edited = [path, b"--config", resolved.as_bytes()];
+    assert_eq!(child.cmdline()?, padded(&edited, len));
+
+    let failed = command(&mut child, "!push_arg", None, Some("more"))?;
+    assert!(failed.starts_with("set failed: Only "), "{failed}");
+    assert_eq!(child.cmdline()?, padded(&edited, len));
+    let failed = command(&mut child, "!set_arg", Some(3), Some("x"))?;
+    assert_eq!(failed, "set failed: There is no argument 3, only 3.");
+
+    // A title starts a new list of arguments, a revert goes back to argv.
+    let title = encode("a");
+    assert_eq!(command(&mut child, &title, None, None)?, "set done");
+    assert_eq!(
+        command(&mut child, "!push_arg", None, Some("b"))?,
+        "set done"
+    );
+    assert!(child.cmdline()?.starts_with(b"a\0b\0"));
+    assert_eq!(command(&mut child, "!revert", None, None)?, "set done");
+    assert_eq!(
+        command(&mut child, "!remove_arg", Some(2), None)?,
+        "set done"
+    );
+    let removed = [path, b"--daemonize", b"/tmp/x"];
+    assert_eq!(child.cmdline()?, padded(&removed, len));
+
+    // The program still sees its arguments as it got them.
+    let args = command(&mut child, "!args", None, None)?;
+    assert!(args.ends_with(" --daemonize --config /tmp/x"), "{args}");
+    assert!(child.wait()?.success());
+    Ok(())
+}
+
+#[test]
+fn test_edit_args_truncate() -> Result<()> {
+    let mut child = spawn(&["--arg-overflow=truncate"])?;
+    let original = child.cmdline()?;
+    let len = original.len();
+
+    let long = "y".repeat(len);
+    assert_eq!(
+        command(&mut child, "!push_arg", None, Some(&long))?,
+        "set done"
+    );
+    let cmdline = child.cmdline()?;
+    // Up to the last NUL byte of argv, so the kernel still shows every element.
+    assert_eq!(cmdline.len(), len);
+    assert_eq!(
+        cmdline[..len - 1],
+        [&original, long.as_bytes()].concat()[..len - 1]
+    );
+    assert_eq!(cmdline[len - 1], 0);
+
+    // Later edits start from what is shown, what was cut off does not come back.
+    assert_eq!(
+        command(&mut child, "!remove_arg", Some(1), None)?,
+        "set done"
+    );
+    assert_eq!(
+        command(&mut child, "!push_arg", None, Some(&long))?,
+        "set done"
+    );
+    let path = original.split(|&b| b == 0).next().unwrap();
+    let shown = &long[..len - 1 - path.len() - 1];
+    assert_eq!(child.cmdline()?, padded(&[path, shown.as_bytes()], len));
+    assert_eq!(
+        command(&mut child, "!set_arg", Some(0), Some("p"))?,
+        "set done"
+    );
+    assert_eq!(child.cmdline()?, padded(&[b"p", shown.as_bytes()], len));
+    assert!(child.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
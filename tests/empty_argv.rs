@@ -0,0 +1,61 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{encode, proc_file, spawn_with_argv};
+
+/// Linux 5.18 and later insert an empty argv[0] when execve() gets none,
+/// older kernels keep argc == 0.
+fn argv0_inserted() -> Result<bool> {
+    let release = std::fs::read_to_string("/proc/sys/kernel/osrelease")?;
+    let mut version = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse::<u32>());
+    let (major, minor) = (version.next().unwrap()?, version.next().unwrap()?);
+    Ok((major, minor) >= (5, 18))
+}
+
+#[test]
+fn test_empty_argv() -> Result<()> {
+    let mut child = spawn_with_argv(&[], &["KILLMYARGV_EMPTY_ARGV=1"])?;
+    let mut stdin = child.stdin.take().unwrap();
+    // Fails when EmptyArgv is returned, the output is checked below.
+    let _ = writeln!(stdin, "{}", encode("empty argv"));
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    // With the inserted argv[0], the title lives in the environ area following its NUL byte.
+    if argv0_inserted()? {
+        assert!(output.status.success(), "{stderr}");
+        assert_eq!(stdout, "set done\n");
+    } else {
+        assert!(!output.status.success());
+        assert!(stderr.contains("EmptyArgv"), "{stderr}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_empty_argv_cmdline() -> Result<()> {
+    if !argv0_inserted()? {
+        // Nothing to render, test_empty_argv checks the error.
+        return Ok(());
+    }
+    let mut child = spawn_with_argv(&[], &["KILLMYARGV_EMPTY_ARGV=1"])?;
+    let pid = child.id();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut reader = linereader::LineReader::new(child.stdout.take().unwrap());
+
+    writeln!(stdin, "{}", encode("empty argv"))?;
+    assert_eq!(reader.next_line().unwrap()?, b"set done\n");
+    assert_eq!(proc_file(pid, "cmdline")?, b"empty argv\0");
+    drop(stdin);
+    child.wait()?;
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::io::Write;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
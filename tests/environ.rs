@@ -0,0 +1,35 @@
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_environ_region_after_setenv() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let envs = [("A", "aaaa"), ("BB", "b")];
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .args(["true", "--setenv-first"])
+            .env_clear()
+            .envs(envs),
+    )?;
+    let max_len: usize = session.next_line()?.parse()?;
+    session.wait()?;
+
+    // The region still ends at the last string the kernel put there,
+    // not at the one setenv allocated.
+    let argv_len = [set_cmdline_path.len(), "true".len(), "--setenv-first".len()]
+        .iter()
+        .map(|len| len + 1)
+        .sum::<usize>();
+    let env_len = envs
+        .iter()
+        .map(|(k, v)| k.len() + 1 + v.len() + 1)
+        .sum::<usize>()
+        - 1;
+    assert_eq!(max_len, argv_len + env_len);
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,49 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, proc_file, Session};
+
+#[test]
+fn test_fork_workers() -> Result<()> {
+    const WORKERS: usize = 8;
+    let mut session = Session::spawn(
+        Command::new(get_set_cmdline_path()?).arg(format!("--fork-workers={WORKERS}")),
+    )?;
+
+    // The workers print in whatever order they get to it.
+    let mut titles = Vec::new();
+    for _ in 0..WORKERS + 1 {
+        let line = session.next_line()?;
+        let (name, pid) = line.split_once(' ').ok_or("bad line")?;
+        let title = match name {
+            "parent" => "parent".to_owned(),
+            index => format!("worker {index}"),
+        };
+        titles.push((title, pid.parse::<u32>()?));
+    }
+    titles.sort();
+    let expected = ["parent".to_owned()]
+        .into_iter()
+        .chain((0..WORKERS).map(|i| format!("worker {i}")))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        titles.iter().map(|(t, _)| t).collect::<Vec<_>>(),
+        expected.iter().collect::<Vec<_>>()
+    );
+
+    for (title, pid) in &titles {
+        let cmdline = proc_file(*pid, "cmdline")?;
+        assert!(
+            cmdline.starts_with(format!("{title}\0").as_bytes()),
+            "{title}: {cmdline:?}"
+        );
+    }
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
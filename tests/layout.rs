@@ -0,0 +1,64 @@
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_layout() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .args(["true", "--layout"])
+            .env_clear()
+            .env("AA", "1")
+            .env("BBB", "22"),
+    )?;
+    let max_len: usize = session.next_line()?.parse()?;
+    let region = session.next_line()?;
+    let argv_entries = session.next_line()?;
+    let environ_entries = session.next_line()?;
+    session.wait()?;
+
+    // Each string starts right after the NUL byte of the previous one.
+    let path_len = set_cmdline_path.len();
+    let argv_len = path_len + 1 + "true".len() + 1 + "--layout".len() + 1;
+    assert_eq!(region, format!("{max_len} Some(0)"));
+    assert_eq!(
+        argv_entries,
+        format!("0:0:{path_len},1:{}:4,2:{}:8", path_len + 1, path_len + 6)
+    );
+    assert_eq!(
+        environ_entries,
+        format!("0:{argv_len}:4,1:{}:6", argv_len + 5)
+    );
+    assert_eq!(max_len, argv_len + "AA=1".len() + 1 + "BBB=22".len());
+    Ok(())
+}
+
+#[test]
+fn test_layout_argv_only() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .args(["true", "--spawn-thread", "--layout"])
+            .env_clear()
+            .env("AA", "1"),
+    )?;
+    let max_len: usize = session.next_line()?.parse()?;
+    let region = session.next_line()?;
+    let _argv_entries = session.next_line()?;
+    let environ_entries = session.next_line()?;
+    session.wait()?;
+
+    // environ is not written to with another thread running, so it is not part of the layout.
+    let argv_len = ["true", "--spawn-thread", "--layout"]
+        .iter()
+        .fold(set_cmdline_path.len(), |len, arg| len + 1 + arg.len());
+    assert_eq!(max_len, argv_len);
+    assert_eq!(region, format!("{max_len} None"));
+    assert_eq!(environ_entries, "");
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,91 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, Session};
+
+// PT_INTERP of a little endian ELF64 binary.
+fn interpreter(path: &OsStr) -> Result<Option<String>> {
+    let elf = fs::read(path)?;
+    if elf.get(..6) != Some(b"\x7fELF\x02\x01") {
+        return Ok(None);
+    }
+    let u16_at = |at: usize| u16::from_le_bytes(elf[at..at + 2].try_into().unwrap()) as usize;
+    let u64_at = |at: usize| u64::from_le_bytes(elf[at..at + 8].try_into().unwrap()) as usize;
+    let (phoff, phentsize, phnum) = (u64_at(0x20), u16_at(0x36), u16_at(0x38));
+    for header in (0..phnum).map(|i| phoff + i * phentsize) {
+        if u32::from_le_bytes(elf[header..header + 4].try_into().unwrap()) == 3 {
+            let (offset, size) = (u64_at(header + 8), u64_at(header + 32));
+            let interp = CStr::from_bytes_until_nul(&elf[offset..offset + size])?;
+            return Ok(Some(interp.to_str()?.to_owned()));
+        }
+    }
+    Ok(None)
+}
+
+#[test]
+fn test_launched_by_loader() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let Some(loader) = interpreter(&set_cmdline_path)? else {
+        eprintln!("no dynamic loader, skipping");
+        return Ok(());
+    };
+    let mut session = Session::spawn(Command::new(&loader).arg(&set_cmdline_path).args([
+        "true",
+        "--layout",
+        "--procfs-region",
+    ]))?;
+    session.next_line()?;
+    session.next_line()?;
+    // The loader slot is part of the region, before argv[0].
+    let argv_entries = session.next_line()?;
+    let argv0 = format!("0:{}:{}", loader.len() + 1, set_cmdline_path.len());
+    assert!(argv_entries.starts_with(&argv0), "{argv_entries}");
+    session.next_line()?;
+
+    session.send_title("loader title")?;
+    assert_eq!(session.next_line()?, "set done");
+    let cmdline = session.cmdline()?;
+    assert!(cmdline.starts_with(b"loader title\0"), "{cmdline:?}");
+    assert!(cmdline[b"loader title".len()..].iter().all(|&b| b == 0));
+
+    session.wait()?;
+    Ok(())
+}
+
+#[test]
+fn test_ensure_capacity_by_loader() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let Some(loader) = interpreter(&set_cmdline_path)? else {
+        eprintln!("no dynamic loader, skipping");
+        return Ok(());
+    };
+    // Restarted through the loader again, which is what /proc/self/exe points to.
+    let mut session = Session::spawn(
+        Command::new(&loader)
+            .arg(&set_cmdline_path)
+            .args(["--ensure-capacity=300", "x"])
+            .env_clear(),
+    )?;
+    let line = session.next_line()?;
+    let mut fields = line.splitn(3, ' ');
+    let max_len: usize = fields.next().ok_or("no max_len")?.parse()?;
+    assert!(max_len >= 300, "{line}");
+    assert_eq!(fields.next(), Some("false"), "{line}");
+    let args = fields.next().ok_or("no args")?;
+    assert!(args.ends_with(" --ensure-capacity=300 x"), "{line}");
+
+    let title = "t".repeat(299);
+    session.send_title(&title)?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_eq!(session.title()?, title.as_bytes());
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,42 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_minimal_clobber() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .args(["true", "--minimal-clobber"])
+            .env("KILLMYARGV_MINIMAL", "1"),
+    )?;
+    let max_len: usize = session.next_line()?.parse()?;
+    let environ = session.proc_file("environ")?;
+    let argv_len = set_cmdline_path.len() + 1 + "true".len() + 1 + "--minimal-clobber".len();
+    assert!(max_len > argv_len + 16);
+
+    let mut set = |title: &str| -> Result<Vec<u8>> {
+        session.send_title(title)?;
+        assert_eq!(session.next_line()?, "set done");
+        assert_eq!(session.title()?, title.as_bytes());
+        session.proc_file("environ")
+    };
+
+    assert_eq!(set("short")?, environ);
+    // Runs over into environ by 8 bytes.
+    let long = "x".repeat(argv_len + 1 + 8);
+    let clobbered = set(&long)?;
+    assert_eq!(clobbered[..8], *b"xxxxxxxx");
+    // Only the title and its NUL byte are written.
+    assert_eq!(clobbered[9..], environ[9..]);
+    assert_eq!(set("short again")?, environ);
+
+    session.wait()?;
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,64 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, Session};
+
+fn spawn(policy: &str) -> Result<Session> {
+    Session::spawn(Command::new(get_set_cmdline_path()?).arg(format!("--modified-policy={policy}")))
+}
+
+#[test]
+fn test_modified_error() -> Result<()> {
+    let mut session = spawn("error")?;
+
+    session.send("!modified")?;
+    assert_eq!(session.next_line()?, "false");
+    session.send_title("first")?;
+    assert_eq!(session.next_line()?, "set done");
+    session.send("!modified")?;
+    assert_eq!(session.next_line()?, "false");
+
+    session.send("!scribble")?;
+    assert_eq!(session.next_line()?, "scribbled");
+    session.send("!modified")?;
+    assert_eq!(session.next_line()?, "true");
+    let scribbled = session.cmdline()?;
+    assert!(scribbled.starts_with(b"First\0"), "{scribbled:?}");
+
+    // Neither set nor revert touch the region now.
+    session.send_title("second")?;
+    assert!(session.next_line()?.starts_with("set failed: "));
+    session.send("!revert")?;
+    assert!(session.next_line()?.starts_with("set failed: "));
+    session.send("!revert_full")?;
+    assert!(session.next_line()?.starts_with("set failed: "));
+    assert_eq!(session.cmdline()?, scribbled);
+
+    session.wait()?;
+    Ok(())
+}
+
+#[test]
+fn test_modified_overwrite() -> Result<()> {
+    let mut session = spawn("overwrite")?;
+
+    // Written behind our back before the first set.
+    session.send("!scribble")?;
+    assert_eq!(session.next_line()?, "scribbled");
+    session.send("!modified")?;
+    assert_eq!(session.next_line()?, "true");
+
+    session.send_title("second")?;
+    assert_eq!(session.next_line()?, "set done");
+    assert!(session.cmdline()?.starts_with(b"second\0"));
+    session.send("!modified")?;
+    assert_eq!(session.next_line()?, "false");
+
+    session.wait()?;
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
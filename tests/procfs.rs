@@ -0,0 +1,102 @@
+use utils::{get_set_cmdline_path, set_cmdline_with_child};
+
+#[test]
+fn test_procfs_region() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let envs = [("A", "aaaa"), ("BB", "b")];
+    let mut child = Command::new(&set_cmdline_path)
+        .args(["true", "--procfs-region"])
+        .env_clear()
+        .envs(envs)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let child_pid = child.id();
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    let mut reader = linereader::LineReader::new(child_stdout);
+    let max_len = reader.next_line().unwrap()?;
+    let max_len: usize = String::from_utf8_lossy(max_len).trim().parse()?;
+
+    // arg_end - arg_start + env_end - env_start, without the last NUL byte.
+    let argv_len = [
+        set_cmdline_path.len(),
+        "true".len(),
+        "--procfs-region".len(),
+    ]
+    .iter()
+    .map(|len| len + 1)
+    .sum::<usize>();
+    let env_len = envs
+        .iter()
+        .map(|(k, v)| k.len() + 1 + v.len() + 1)
+        .sum::<usize>();
+    assert_eq!(max_len, argv_len + env_len - 1);
+
+    let input = "o".repeat(max_len);
+    set_cmdline_with_child(
+        [input.clone()],
+        [vec![input]],
+        child_stdin,
+        reader.into_inner(),
+        child_pid,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_procfs_region_unreachable() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let envs = [("A", "aaaa"), ("BB", "b")];
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--procfs-region", "--unsetenv=BB"])
+        .env_clear()
+        .envs(envs)
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+
+    // The last environ string is no longer reachable from the pointers,
+    // it is still rendered by the kernel and part of the region.
+    let argv_len = [
+        set_cmdline_path.len(),
+        "true".len(),
+        "--procfs-region".len(),
+        "--unsetenv=BB".len(),
+    ]
+    .iter()
+    .map(|len| len + 1)
+    .sum::<usize>();
+    let env_len = envs
+        .iter()
+        .map(|(k, v)| k.len() + 1 + v.len() + 1)
+        .sum::<usize>();
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(stdout, format!("{}\n", argv_len + env_len - 1));
+    Ok(())
+}
+
+#[test]
+fn test_procfs_region_mismatch() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["true", "--procfs-region", "--unsetenv=A"])
+        .env_clear()
+        .envs([("A", "aaaa"), ("BB", "b")])
+        .stdin(Stdio::null())
+        .output()?;
+
+    // environ no longer starts where the kernel's area does.
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ProcfsMismatch"), "{stderr}");
+    assert!(stderr.contains("\"environ\""), "{stderr}");
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::{Command, Stdio};
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,60 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_revert_full() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .arg("--layout")
+            .env("KILLMYARGV_REVERT", "1"),
+    )?;
+    // The layout, printed once initialization is done.
+    for _ in 0..3 {
+        session.next_line()?;
+    }
+    let cmdline = session.cmdline()?;
+    let environ = session.proc_file("environ")?;
+
+    // Long enough to run over into environ.
+    session.send_title(&"x".repeat(cmdline.len() + 16))?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_ne!(session.proc_file("environ")?, environ);
+
+    session.send("!revert_full")?;
+    assert_eq!(session.next_line()?, "true");
+    assert_eq!(session.cmdline()?, cmdline);
+    assert_eq!(session.proc_file("environ")?, environ);
+
+    session.wait()?;
+    Ok(())
+}
+
+#[test]
+fn test_revert() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&set_cmdline_path)
+            .arg("true")
+            .env("KILLMYARGV_REVERT", "1"),
+    )?;
+    session.next_line()?;
+    let cmdline = session.cmdline()?;
+
+    session.send_title(&"x".repeat(cmdline.len() + 16))?;
+    assert_eq!(session.next_line()?, "set done");
+    // argv is written back up to and including its last NUL byte, which stays NUL.
+    session.send("!revert")?;
+    assert_eq!(session.next_line()?, "set done");
+    assert_eq!(session.cmdline()?, cmdline);
+
+    session.wait()?;
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
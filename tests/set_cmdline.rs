@@ -45,6 +45,72 @@ fn test_set_cmdline_truncate_max_len() -> Result<()> {
     Ok(())
 }
 
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn test_set_cmdline_from_raw_parts() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut child = Command::new(&set_cmdline_path)
+        .args(["true", "--raw-parts"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let child_pid = child.id();
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    let mut reader = linereader::LineReader::new(child_stdout);
+    let max_len = reader.next_line().unwrap()?;
+    let max_len: usize = String::from_utf8_lossy(max_len).trim().parse()?;
+    assert!(max_len > set_cmdline_path.len() + "true".len() + "--raw-parts".len());
+
+    set_cmdline_with_child(
+        ["Hi\0there!"],
+        [vec!["Hi", "there!"]],
+        child_stdin,
+        reader.into_inner(),
+        child_pid,
+    )?;
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn test_raw_parts_mismatch() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["--raw-parts-mismatch", "x"])
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    // Refused, and argc_argv() still reports the argv found first.
+    assert!(stdout.starts_with("Err(ArgvMismatch {"), "{stdout}");
+    assert!(
+        stdout.ends_with("found_by: \"init_array\" }) 3\n"),
+        "{stdout}"
+    );
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn test_global_from_raw_parts() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(&set_cmdline_path)
+        .args(["--global-raw-parts", "x"])
+        .stdin(Stdio::null())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let line = stdout.lines().next().unwrap();
+    assert!(
+        line.starts_with("Ok(\"raw_parts\") Err(ArgvMismatch {"),
+        "{line}"
+    );
+    assert!(line.ends_with("found_by: \"raw_parts\" })"), "{line}");
+    Ok(())
+}
+
 mod utils;
 
 use std::error::Error;
@@ -0,0 +1,63 @@
+#![cfg(all(target_os = "linux", target_env = "gnu"))]
+
+use utils::{build_c_host, build_cdylib, Session};
+
+#[test]
+fn test_bsd_setproctitle() -> Result<()> {
+    let lib = build_cdylib("setproctitle", "setproctitle")?;
+    let lib_dir = lib.parent().unwrap();
+    let mut rpath = OsString::from("-Wl,-rpath,");
+    rpath.push(lib_dir);
+    let mut lib_arg = OsString::from("-L");
+    lib_arg.push(lib_dir);
+    let args = [
+        lib_arg.as_os_str(),
+        "-lsetproctitle".as_ref(),
+        rpath.as_os_str(),
+    ];
+    let Some(host) = build_c_host("setproctitle", &args)? else {
+        eprintln!("no C compiler, skipping");
+        return Ok(());
+    };
+    let mut session = Session::spawn(Command::new(&host).arg("--daemon"))?;
+
+    for (input, title) in [
+        ("accepting", "host: accepting 42 1.5"),
+        ("-no progname", "no progname 42 1.5"),
+        ("!restore", host.to_str().unwrap()),
+    ] {
+        session.send(input)?;
+        assert_eq!(session.next_line()?, "set done");
+        assert_eq!(String::from_utf8(session.title()?)?, title);
+    }
+    let restored = session.cmdline()?;
+    assert_eq!(
+        restored,
+        [host.as_os_str().as_bytes(), b"\0--daemon\0"].concat()
+    );
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+#[test]
+fn test_bsd_setproctitle_exports() -> Result<()> {
+    let lib = build_cdylib("setproctitle", "setproctitle")?;
+    let path = CString::new(lib.into_os_string().into_vec())?;
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    assert!(!handle.is_null());
+    let symbol = |name: &CStr| unsafe { libc::dlsym(handle, name.as_ptr()) };
+    assert!(!symbol(c"setproctitle").is_null());
+    assert!(!symbol(c"setproctitle_init").is_null());
+    // Only format.c calls it.
+    assert!(symbol(c"killmyargv_setproctitle_title").is_null());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::ffi::{CStr, CString, OsString};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
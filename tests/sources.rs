@@ -0,0 +1,89 @@
+use utils::{get_set_cmdline_path, set_cmdline_with_child};
+
+fn set_cmdline_with_source(source: &str) -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut command = Command::new(set_cmdline_path);
+    command.arg(format!("--source={source}"));
+    set_cmdline_with_command(command, source)
+}
+
+fn set_cmdline_with_command(mut command: Command, source: &str) -> Result<()> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let child_pid = child.id();
+    let child_stdin = child.stdin.take().unwrap();
+    let child_stdout = child.stdout.take().unwrap();
+
+    let mut reader = linereader::LineReader::new(child_stdout);
+    let used = reader.next_line().unwrap()?;
+    assert_eq!(String::from_utf8_lossy(used).trim(), source);
+
+    set_cmdline_with_child(
+        ["Hi\0there!"],
+        [vec!["Hi", "there!"]],
+        child_stdin,
+        reader.into_inner(),
+        child_pid,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_source_init_array() -> Result<()> {
+    set_cmdline_with_source("init_array")
+}
+
+#[test]
+fn test_source_computed() -> Result<()> {
+    set_cmdline_with_source("computed")
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_source_procfs() -> Result<()> {
+    set_cmdline_with_source("procfs")
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_source_auxv() -> Result<()> {
+    set_cmdline_with_source("auxv")
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn test_source_auxv_empty_environ() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut command = Command::new(set_cmdline_path);
+    command.arg("--source=auxv").env_clear();
+    set_cmdline_with_command(command, "auxv")
+}
+
+#[test]
+fn test_source_stack_walking() -> Result<()> {
+    set_cmdline_with_source("stack_walking")
+}
+
+#[test]
+fn test_source_stack_walking_max_argc() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let output = Command::new(set_cmdline_path)
+        .args(["--source=stack_walking", "--max-argc=1"])
+        .stdin(Stdio::null())
+        .output()?;
+
+    // argc is 3, the walk has to give up instead of going on forever.
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("StackWalkFailed"), "{stderr}");
+    assert!(stderr.contains("maximum"), "{stderr}");
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::{Command, Stdio};
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
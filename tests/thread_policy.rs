@@ -0,0 +1,25 @@
+use utils::{get_set_cmdline_path, Session};
+
+#[test]
+fn test_argv_only_when_threaded() -> Result<()> {
+    let set_cmdline_path = get_set_cmdline_path()?;
+    let mut session =
+        Session::spawn(Command::new(&set_cmdline_path).args(["true", "--spawn-thread"]))?;
+    let max_len: usize = session.next_line()?.parse()?;
+    session.wait()?;
+
+    // Only the argv area is usable, without its terminating NUL byte.
+    let argv_len = [set_cmdline_path.len(), "true".len(), "--spawn-thread".len()]
+        .iter()
+        .map(|len| len + 1)
+        .sum::<usize>()
+        - 1;
+    assert_eq!(max_len, argv_len);
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -0,0 +1,96 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use utils::{get_set_cmdline_path, spawn_with_argv, Session};
+
+fn check_prefix(mode: &str, prefix: impl Fn(&[u8]) -> Vec<u8>, with_environ: bool) -> Result<()> {
+    let path = get_set_cmdline_path()?;
+    let mut session = Session::spawn(
+        Command::new(&path)
+            .arg("true")
+            .arg(format!("--title-prefix={mode}"))
+            .env_clear()
+            .env("A", "1"),
+    )?;
+
+    let max_len: usize = session.next_line()?.parse()?;
+    let prefix = prefix(path.as_bytes());
+    // The whole region or only argv, without its final NUL byte, less the prefix.
+    let mut region = session.cmdline()?.len() - 1;
+    if with_environ {
+        region += session.proc_file("environ")?.len();
+    }
+    assert_eq!(max_len, region - prefix.len(), "{mode}");
+
+    for title in ["idle".to_owned(), "x".repeat(max_len)] {
+        session.send_title(&title)?;
+        assert_eq!(session.next_line()?, "set done");
+        let cmdline = session.cmdline()?;
+        let expected = [prefix.as_slice(), title.as_bytes()].concat();
+        assert!(cmdline.starts_with(&expected), "{mode}: {cmdline:?}");
+    }
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+fn basename(path: &[u8]) -> &[u8] {
+    path.rsplit(|&b| b == b'/').next().unwrap()
+}
+
+#[test]
+fn test_title_prefix_argv0() -> Result<()> {
+    check_prefix("argv0", |path| [path, b"\0"].concat(), false)
+}
+
+#[test]
+fn test_title_prefix_basename() -> Result<()> {
+    check_prefix("basename", |path| [basename(path), b"\0"].concat(), false)
+}
+
+#[test]
+fn test_title_prefix_progname() -> Result<()> {
+    check_prefix("progname", |path| [basename(path), b": "].concat(), true)
+}
+
+#[test]
+fn test_title_prefix_fills_argv() -> Result<()> {
+    // Nothing but a long argv[0], without a directory the basename is all of it.
+    let argv0 = "x".repeat(300);
+    for mode in ["argv0", "basename"] {
+        let child = spawn_with_argv(&[&argv0], &[&format!("KILLMYARGV_TITLE_PREFIX={mode}")])?;
+        let output = child.wait_with_output()?;
+        assert!(!output.status.success(), "{mode}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("InsufficientCapacity"), "{mode}: {stderr}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_title_prefix_basename_long_argv0() -> Result<()> {
+    let argv0 = format!("/{}/daemon", "d".repeat(300));
+    let mut session = Session::from_child(spawn_with_argv(
+        &[&argv0, "true"],
+        &["KILLMYARGV_TITLE_PREFIX=basename"],
+    )?);
+
+    // The rest of argv is left for the title.
+    let max_len: usize = session.next_line()?.parse()?;
+    assert_eq!(max_len, argv0.len() + "\0true".len() - "daemon\0".len());
+    let title = "t".repeat(max_len);
+    session.send_title(&title)?;
+    assert_eq!(session.next_line()?, "set done");
+    assert!(session
+        .cmdline()?
+        .starts_with(format!("daemon\0{title}").as_bytes()));
+
+    assert!(session.wait()?.success());
+    Ok(())
+}
+
+mod utils;
+
+use std::error::Error;
+use std::os::unix::ffi::OsStrExt;
+use std::process::Command;
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 use base64::alphabet::STANDARD;
 use base64::engine::GeneralPurpose;
 use base64::Engine;
@@ -8,6 +10,160 @@ pub fn get_set_cmdline_path() -> Result<OsString> {
     Ok(build_test_binary("set_cmdline_from_stdin", "testbin")?)
 }
 
+/// Builds the `cdylib` crate at `path` in the repository, test_binary only knows about binaries.
+pub fn build_cdylib(path: &str, lib_name: &str) -> Result<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(path);
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--quiet"])
+        .current_dir(&dir)
+        .status()?;
+    assert!(status.success(), "failed to build {path}");
+    Ok(dir.join(format!("target/debug/lib{lib_name}.so")))
+}
+
+/// Compiles `testbin/<name>/host.c` with the system C compiler,
+/// `None` when there is none.
+pub fn build_c_host(name: &str, args: &[&OsStr]) -> Result<Option<PathBuf>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testbin")
+        .join(name);
+    let host = dir.join("target/host");
+    std::fs::create_dir_all(dir.join("target"))?;
+    let cc = std::env::var_os("CC").unwrap_or("cc".into());
+    let status = match Command::new(cc)
+        .arg("-o")
+        .arg(&host)
+        .arg(dir.join("host.c"))
+        .args(args)
+        .status()
+    {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    assert!(status.success(), "failed to build the host of {name}");
+    Ok(Some(host))
+}
+
+/// Spawns the test binary with exactly the given argv, which may be empty,
+/// something `Command` can not do on its own.
+pub fn spawn_with_argv(argv: &[&str], envs: &[&str]) -> Result<Child> {
+    let path = CString::new(get_set_cmdline_path()?.into_vec())?;
+    let to_cstrings = |strs: &[&str]| {
+        strs.iter()
+            .map(|s| CString::new(*s))
+            .collect::<std::result::Result<Vec<_>, _>>()
+    };
+    let (argv, envs) = (to_cstrings(argv)?, to_cstrings(envs)?);
+    // Nothing may be allocated between fork and exec, so the arrays are built here.
+    let to_ptrs = |strs: &[CString]| {
+        strs.iter()
+            .map(|s| s.as_ptr() as usize)
+            .chain([0])
+            .collect::<Vec<usize>>()
+    };
+    let (argv_ptrs, env_ptrs) = (to_ptrs(&argv), to_ptrs(&envs));
+    let mut command = Command::new("/nonexistent");
+    unsafe {
+        command.pre_exec(move || {
+            let _keep_alive = (&argv, &envs);
+            libc::execve(
+                path.as_ptr(),
+                argv_ptrs.as_ptr() as *const *const c_char,
+                env_ptrs.as_ptr() as *const *const c_char,
+            );
+            Err(io::Error::last_os_error())
+        })
+    };
+    Ok(command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?)
+}
+
+/// A spawned test program, fed line by line through its stdin and answering on its stdout.
+pub struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    reader: linereader::LineReader<ChildStdout>,
+}
+
+impl Session {
+    /// Spawns `command` with piped stdin and stdout.
+    pub fn spawn(command: &mut Command) -> Result<Session> {
+        Ok(Session::from_child(
+            command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?,
+        ))
+    }
+
+    /// Takes over a child spawned with piped stdin and stdout.
+    pub fn from_child(mut child: Child) -> Session {
+        Session {
+            stdin: child.stdin.take().unwrap(),
+            reader: linereader::LineReader::new(child.stdout.take().unwrap()),
+            child,
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// The next line of output without surrounding whitespace.
+    pub fn next_line(&mut self) -> Result<String> {
+        let line = self.reader.next_line().ok_or("exited early")??;
+        Ok(String::from_utf8_lossy(line).trim().to_owned())
+    }
+
+    pub fn send(&mut self, line: &str) -> Result<()> {
+        writeln!(self.stdin, "{line}")?;
+        Ok(())
+    }
+
+    /// Sends a title the way set_cmdline_from_stdin reads it, base64 encoded.
+    pub fn send_title(&mut self, title: &str) -> Result<()> {
+        self.send(&encode(title))
+    }
+
+    pub fn proc_file(&self, name: &str) -> Result<Vec<u8>> {
+        proc_file(self.pid(), name)
+    }
+
+    pub fn cmdline(&self) -> Result<Vec<u8>> {
+        self.proc_file("cmdline")
+    }
+
+    /// The cmdline up to its first NUL byte.
+    pub fn title(&self) -> Result<Vec<u8>> {
+        let mut cmdline = self.cmdline()?;
+        if let Some(end) = cmdline.iter().position(|&b| b == 0) {
+            cmdline.truncate(end);
+        }
+        Ok(cmdline)
+    }
+
+    /// Closes stdin and waits for the program to exit.
+    pub fn wait(self) -> Result<ExitStatus> {
+        let Session {
+            mut child, stdin, ..
+        } = self;
+        drop(stdin);
+        Ok(child.wait()?)
+    }
+}
+
+pub fn proc_file(pid: u32, name: &str) -> Result<Vec<u8>> {
+    Ok(std::fs::read(format!("/proc/{pid}/{name}"))?)
+}
+
+pub fn encode(title: &str) -> String {
+    GeneralPurpose::new(&STANDARD, Default::default()).encode(title)
+}
+
 pub fn set_cmdline(
     inputs: impl IntoIterator<Item = impl AsRef<str>>,
     results: impl IntoIterator<Item = impl IntoIterator<Item = impl AsRef<str>>>,
@@ -42,8 +198,8 @@ pub fn set_cmdline_with_child(
             .bytes()
             .filter(|ch| ch != &b'\n')
             .collect::<Vec<_>>();
-        child_stdin.write(&case_base64)?;
-        child_stdin.write(&[b'\n'])?;
+        child_stdin.write_all(&case_base64)?;
+        child_stdin.write_all(b"\n")?;
         child_stdin.flush()?;
 
         if let Some(line) = child_stdout.next_line() {
@@ -67,8 +223,10 @@ pub fn set_cmdline_with_child(
 
 use std::{
     error::Error,
-    ffi::OsString,
-    io::Write,
-    process::{ChildStdin, ChildStdout, Stdio},
+    ffi::{c_char, CString, OsStr, OsString},
+    io::{self, Write},
+    os::unix::{ffi::OsStringExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
 };
 type Result<T> = std::result::Result<T, Box<dyn Error>>;